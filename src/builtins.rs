@@ -0,0 +1,381 @@
+use crate::input::Span;
+use crate::interpret::{position, Context, Object, RuntimeError};
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::Signed;
+use std::{cell::RefCell, rc::Rc};
+
+pub type BuiltinFn = fn(&[Object], Span) -> Result<Object, RuntimeError>;
+
+const BUILTINS: [(&str, usize, BuiltinFn); 11] = [
+    ("float", 1, float),
+    ("int", 1, int),
+    ("len", 1, len),
+    ("push", 2, push),
+    ("pop", 1, pop),
+    ("insert", 3, insert),
+    ("remove", 2, remove),
+    ("keys", 1, keys),
+    ("values", 1, values),
+    ("has", 2, has),
+    ("delete", 2, delete),
+];
+
+// Methods by the type of their receiver, which is passed as the first argument
+// and counted in the arity. `Number` covers every numeric type.
+const METHODS: [(&str, &str, usize, BuiltinFn); 29] = [
+    ("String", "len", 1, len),
+    ("String", "upper", 1, upper),
+    ("String", "lower", 1, lower),
+    ("String", "trim", 1, trim),
+    ("String", "split", 2, split),
+    ("String", "contains", 2, contains),
+    ("String", "starts_with", 2, starts_with),
+    ("String", "ends_with", 2, ends_with),
+    ("String", "replace", 3, replace),
+    ("List", "len", 1, len),
+    ("List", "push", 2, push),
+    ("List", "pop", 1, pop),
+    ("List", "insert", 3, insert),
+    ("List", "remove", 2, remove),
+    ("List", "contains", 2, contains),
+    ("List", "map", 2, map),
+    ("List", "filter", 2, filter),
+    ("List", "join", 2, join),
+    ("List", "reverse", 1, reverse),
+    ("Map", "len", 1, len),
+    ("Map", "keys", 1, keys),
+    ("Map", "values", 1, values),
+    ("Map", "has", 2, has),
+    ("Map", "delete", 2, delete),
+    ("Number", "sqrt", 1, sqrt),
+    ("Number", "abs", 1, abs),
+    ("Number", "floor", 1, floor),
+    ("Number", "ceil", 1, ceil),
+    ("Number", "round", 1, round),
+];
+
+pub fn method(
+    value: &Object,
+    name: &str,
+) -> Option<(&'static str, usize, BuiltinFn)> {
+    let kind = match value.to_f64() {
+        Some(_) => "Number",
+        None => value.type_name(),
+    };
+    METHODS
+        .iter()
+        .find(|(receiver, method, _, _)| *receiver == kind && *method == name)
+        .map(|&(_, name, arity, function)| (name, arity, function))
+}
+
+pub fn register(context: &Context) {
+    context.declare("pi", Object::Float(std::f64::consts::PI));
+    for (name, arity, function) in BUILTINS {
+        context.declare(name, Object::Builtin(name, arity, function));
+    }
+}
+
+fn float(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    match args[0].to_f64() {
+        Some(x) => Ok(Object::Float(x)),
+        None => Err(RuntimeError::TypeMismatch(
+            "number",
+            args[0].type_name(),
+            span,
+        )),
+    }
+}
+
+fn int(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    match &args[0] {
+        Object::Int(_) | Object::BigInt(_) => Ok(args[0].clone()),
+        Object::Rational(x) => Ok(Object::from_bigint(x.to_integer())),
+        Object::Float(x) => match BigRational::from_float(x.trunc()) {
+            Some(x) => Ok(Object::from_bigint(x.to_integer())),
+            None => {
+                Err(RuntimeError::TypeMismatch("finite Float", "Float", span))
+            }
+        },
+        x => Err(RuntimeError::TypeMismatch("number", x.type_name(), span)),
+    }
+}
+
+fn len(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    match &args[0] {
+        Object::String(x) => Ok(Object::Int(x.chars().count() as i64)),
+        Object::List(x) => Ok(Object::Int(x.borrow().len() as i64)),
+        Object::Map(x) => Ok(Object::Int(x.borrow().len() as i64)),
+        Object::Range(start, end) => Ok(Object::Int(
+            end.checked_sub(*start).map_or(i64::MAX, |len| len.max(0)),
+        )),
+        x => Err(RuntimeError::TypeMismatch(
+            "String, List, Map or Range",
+            x.type_name(),
+            span,
+        )),
+    }
+}
+
+fn push(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    match &args[0] {
+        Object::List(x) => {
+            x.borrow_mut().push(args[1].clone());
+            Ok(Object::Null)
+        }
+        x => Err(RuntimeError::TypeMismatch("List", x.type_name(), span)),
+    }
+}
+
+fn pop(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    match &args[0] {
+        Object::List(x) => x
+            .borrow_mut()
+            .pop()
+            .ok_or(RuntimeError::IndexOutOfRange(-1, 0, span)),
+        x => Err(RuntimeError::TypeMismatch("List", x.type_name(), span)),
+    }
+}
+
+fn insert(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    let Object::List(x) = &args[0] else {
+        return Err(RuntimeError::TypeMismatch(
+            "List",
+            args[0].type_name(),
+            span,
+        ));
+    };
+    let index = args[1].as_index(span)?;
+    let mut x = x.borrow_mut();
+    // One past the last item is a valid place to insert at.
+    match position(index, x.len() + 1) {
+        Some(i) => x.insert(i, args[2].clone()),
+        None => {
+            return Err(RuntimeError::IndexOutOfRange(index, x.len(), span))
+        }
+    }
+    Ok(Object::Null)
+}
+
+fn remove(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    let Object::List(x) = &args[0] else {
+        return Err(RuntimeError::TypeMismatch(
+            "List",
+            args[0].type_name(),
+            span,
+        ));
+    };
+    let index = args[1].as_index(span)?;
+    let mut x = x.borrow_mut();
+    match position(index, x.len()) {
+        Some(i) => Ok(x.remove(i)),
+        None => Err(RuntimeError::IndexOutOfRange(index, x.len(), span)),
+    }
+}
+
+fn keys(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    match &args[0] {
+        Object::Map(x) => Ok(Object::List(Rc::new(RefCell::new(
+            x.borrow().iter().map(|(key, _)| key.to_object()).collect(),
+        )))),
+        x => Err(RuntimeError::TypeMismatch("Map", x.type_name(), span)),
+    }
+}
+
+fn values(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    match &args[0] {
+        Object::Map(x) => Ok(Object::List(Rc::new(RefCell::new(
+            x.borrow().iter().map(|(_, value)| value.clone()).collect(),
+        )))),
+        x => Err(RuntimeError::TypeMismatch("Map", x.type_name(), span)),
+    }
+}
+
+fn has(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    match &args[0] {
+        Object::Map(x) => Ok(Object::Bool(
+            x.borrow().contains_key(&args[1].as_key(span)?),
+        )),
+        x => Err(RuntimeError::TypeMismatch("Map", x.type_name(), span)),
+    }
+}
+
+fn delete(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    match &args[0] {
+        Object::Map(x) => {
+            let key = args[1].as_key(span)?;
+            let removed = x.borrow_mut().remove(&key);
+            removed.ok_or(RuntimeError::MissingKey(key.to_string(), span))
+        }
+        x => Err(RuntimeError::TypeMismatch("Map", x.type_name(), span)),
+    }
+}
+
+fn string(value: &Object, span: Span) -> Result<&str, RuntimeError> {
+    match value {
+        Object::String(x) => Ok(x),
+        x => Err(RuntimeError::TypeMismatch("String", x.type_name(), span)),
+    }
+}
+
+fn list(
+    value: &Object,
+    span: Span,
+) -> Result<&Rc<RefCell<Vec<Object>>>, RuntimeError> {
+    match value {
+        Object::List(x) => Ok(x),
+        x => Err(RuntimeError::TypeMismatch("List", x.type_name(), span)),
+    }
+}
+
+fn new_list(items: Vec<Object>) -> Object {
+    Object::List(Rc::new(RefCell::new(items)))
+}
+
+fn upper(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    Ok(Object::String(string(&args[0], span)?.to_uppercase()))
+}
+
+fn lower(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    Ok(Object::String(string(&args[0], span)?.to_lowercase()))
+}
+
+fn trim(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    Ok(Object::String(string(&args[0], span)?.trim().to_string()))
+}
+
+fn split(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    let x = string(&args[0], span)?;
+    let separator = string(&args[1], span)?;
+    // Splitting on an empty separator gives the characters.
+    let parts: Vec<Object> = if separator.is_empty() {
+        x.chars().map(|c| Object::String(c.to_string())).collect()
+    } else {
+        x.split(separator)
+            .map(|part| Object::String(part.to_string()))
+            .collect()
+    };
+    Ok(new_list(parts))
+}
+
+fn contains(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    match &args[0] {
+        Object::List(x) => Ok(Object::Bool(
+            x.borrow().iter().any(|item| item.equals(&args[1])),
+        )),
+        x => Ok(Object::Bool(
+            string(x, span)?.contains(string(&args[1], span)?),
+        )),
+    }
+}
+
+fn starts_with(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    let x = string(&args[0], span)?;
+    Ok(Object::Bool(x.starts_with(string(&args[1], span)?)))
+}
+
+fn ends_with(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    let x = string(&args[0], span)?;
+    Ok(Object::Bool(x.ends_with(string(&args[1], span)?)))
+}
+
+fn replace(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    let x = string(&args[0], span)?;
+    let from = string(&args[1], span)?;
+    Ok(Object::String(x.replace(from, string(&args[2], span)?)))
+}
+
+fn map(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    let items = list(&args[0], span)?.borrow().clone();
+    let mut mapped = Vec::with_capacity(items.len());
+    for item in items {
+        mapped.push(args[1].call(vec![item], span)?);
+    }
+    Ok(new_list(mapped))
+}
+
+fn filter(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    let items = list(&args[0], span)?.borrow().clone();
+    let mut kept = vec![];
+    for item in items {
+        match args[1].call(vec![item.clone()], span)? {
+            Object::Bool(true) => kept.push(item),
+            Object::Bool(false) => {}
+            x => {
+                return Err(RuntimeError::TypeMismatch(
+                    "Bool",
+                    x.type_name(),
+                    span,
+                ))
+            }
+        }
+    }
+    Ok(new_list(kept))
+}
+
+fn join(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    let separator = string(&args[1], span)?;
+    let items = list(&args[0], span)?.borrow();
+    let parts: Vec<String> = items.iter().map(Object::to_string).collect();
+    Ok(Object::String(parts.join(separator)))
+}
+
+fn reverse(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    let mut items = list(&args[0], span)?.borrow().clone();
+    items.reverse();
+    Ok(new_list(items))
+}
+
+fn sqrt(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    float(args, span).map(|x| match x {
+        Object::Float(x) => Object::Float(x.sqrt()),
+        x => x,
+    })
+}
+
+fn abs(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    match &args[0] {
+        Object::Int(x) => Ok(match x.checked_abs() {
+            Some(x) => Object::Int(x),
+            None => Object::from_bigint(BigInt::from(*x).abs()),
+        }),
+        Object::BigInt(x) => Ok(Object::from_bigint(x.abs())),
+        Object::Rational(x) => Ok(Object::Rational(x.abs())),
+        Object::Float(x) => Ok(Object::Float(x.abs())),
+        x => Err(RuntimeError::TypeMismatch("number", x.type_name(), span)),
+    }
+}
+
+fn rounding(
+    value: &Object,
+    span: Span,
+    rational: fn(&BigRational) -> BigRational,
+    float: fn(f64) -> f64,
+) -> Result<Object, RuntimeError> {
+    match value {
+        Object::Int(_) | Object::BigInt(_) => Ok(value.clone()),
+        Object::Rational(x) => {
+            Ok(Object::from_bigint(rational(x).to_integer()))
+        }
+        Object::Float(x) => match BigRational::from_float(float(*x)) {
+            Some(x) => Ok(Object::from_bigint(x.to_integer())),
+            None => {
+                Err(RuntimeError::TypeMismatch("finite Float", "Float", span))
+            }
+        },
+        x => Err(RuntimeError::TypeMismatch("number", x.type_name(), span)),
+    }
+}
+
+fn floor(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    rounding(&args[0], span, BigRational::floor, f64::floor)
+}
+
+fn ceil(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    rounding(&args[0], span, BigRational::ceil, f64::ceil)
+}
+
+fn round(args: &[Object], span: Span) -> Result<Object, RuntimeError> {
+    rounding(&args[0], span, BigRational::round, f64::round)
+}
@@ -1 +1,120 @@
-pub fn get_char() -> char;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// Converts the char offsets stored in spans into line/column positions.
+pub struct SourceMap<'a> {
+    name: &'a str,
+    source: &'a str,
+    line_starts: Vec<usize>,
+    len: usize,
+}
+
+impl<'a> SourceMap<'a> {
+    pub fn new(name: &'a str, source: &'a str) -> SourceMap<'a> {
+        let mut line_starts = vec![0];
+        let mut len = 0;
+        for (i, c) in source.chars().enumerate() {
+            if c == '\n' {
+                line_starts.push(i + 1);
+            }
+            len = i + 1;
+        }
+        SourceMap {
+            name,
+            source,
+            line_starts,
+            len,
+        }
+    }
+
+    /// Zero based line and column of `offset`. Offsets pointing into
+    /// trailing whitespace (usually the end of input) are moved back to
+    /// the last visible character so they land on a meaningful line.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let visible = self.source.trim_end().chars().count();
+        let offset = if offset >= visible {
+            visible.min(self.len)
+        } else {
+            offset
+        };
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        (line, offset - self.line_starts[line])
+    }
+
+    pub fn line(&self, line: usize) -> &'a str {
+        let text = self.source.split('\n').nth(line).unwrap_or("");
+        text.strip_suffix('\r').unwrap_or(text)
+    }
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn new(message: String, span: Span) -> Diagnostic {
+        Diagnostic { message, span }
+    }
+
+    /// Renders the diagnostic rustc style, underlining the span on the
+    /// offending source line. Spans crossing a line break are underlined
+    /// up to the end of their first line.
+    pub fn render(&self, map: &SourceMap) -> String {
+        let (line, col) = map.line_col(self.span.start);
+        let text = map.line(line);
+        let line_len = text.chars().count();
+        let width = if self.span.end > self.span.start {
+            (self.span.end - self.span.start).min(line_len.saturating_sub(col))
+        } else {
+            0
+        }
+        .max(1);
+        let number = (line + 1).to_string();
+        let gutter = " ".repeat(number.len());
+        let indent: String = text
+            .chars()
+            .take(col)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        format!(
+            "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+            self.message,
+            gutter,
+            map.name,
+            line + 1,
+            col + 1,
+            gutter,
+            number,
+            text,
+            gutter,
+            indent,
+            "^".repeat(width),
+        )
+    }
+}
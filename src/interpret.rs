@@ -1,212 +1,1913 @@
-use crate::parser::AST;
+use crate::builtins::{self, BuiltinFn};
+use crate::input::{Diagnostic, Span};
+use crate::parser::{Pattern, AST};
 
-use std::{collections::HashMap, fmt, mem};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, fmt, rc::Rc};
 
-#[derive(Clone, Debug)]
+#[derive(Debug, Default)]
+struct Scope {
+    variables: HashMap<String, Object>,
+    parent: Option<Context>,
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct Context {
-    pub variables: HashMap<String, Object>,
+    scope: Rc<RefCell<Scope>>,
+}
+
+// Exact powers whose result would need more bits than this are rejected
+// rather than computed, `2 ** 4000000000` would take half a gigabyte.
+const MAX_POW_BITS: u64 = 1 << 20;
+
+impl Context {
+    pub fn new() -> Context {
+        Context::default()
+    }
+
+    pub fn child(&self) -> Context {
+        Context {
+            scope: Rc::new(RefCell::new(Scope {
+                variables: HashMap::new(),
+                parent: Some(self.clone()),
+            })),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
+        let scope = self.scope.borrow();
+        match scope.variables.get(name) {
+            Some(value) => Some(value.clone()),
+            None => scope.parent.as_ref()?.get(name),
+        }
+    }
+
+    pub fn declare(&self, name: &str, value: Object) {
+        self.scope
+            .borrow_mut()
+            .variables
+            .insert(name.to_string(), value);
+    }
+
+    fn assign(&self, name: &str, value: Object) -> bool {
+        let mut scope = self.scope.borrow_mut();
+        if let Some(slot) = scope.variables.get_mut(name) {
+            *slot = value;
+            return true;
+        }
+        match &scope.parent {
+            Some(parent) => parent.assign(name, value),
+            None => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    Int(BigInt),
+    String(String),
+    Bool(bool),
+}
+
+impl Key {
+    pub fn to_object(&self) -> Object {
+        match self {
+            Key::Int(x) => Object::from_bigint(x.clone()),
+            Key::String(x) => Object::String(x.clone()),
+            Key::Bool(x) => Object::Bool(*x),
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::Int(x) => write!(f, "{}", x),
+            Key::String(x) => write!(f, "{:?}", x),
+            Key::Bool(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+// Entries are kept in insertion order so iterating and printing a map gives
+// the same output on every run.
+#[derive(Clone, Debug, Default)]
+pub struct Map {
+    entries: Vec<(Key, Object)>,
+    index: HashMap<Key, usize>,
+}
+
+impl Map {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &Key) -> Option<&Object> {
+        Some(&self.entries[*self.index.get(key)?].1)
+    }
+
+    pub fn contains_key(&self, key: &Key) -> bool {
+        self.index.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: Key, value: Object) {
+        match self.index.get(&key) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.index.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &Key) -> Option<Object> {
+        let i = self.index.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+        for (key, _) in &self.entries[i..] {
+            if let Some(slot) = self.index.get_mut(key) {
+                *slot -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &Object)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+}
+
+#[derive(Debug)]
+pub struct StructType {
+    pub name: String,
+    pub fields: Vec<String>,
+    pub methods: RefCell<HashMap<String, Object>>,
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub kind: Rc<StructType>,
+    pub values: Vec<Object>,
+}
+
+impl fmt::Display for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.values.is_empty() {
+            return write!(f, "{} {{}}", self.kind.name);
+        }
+        write!(f, "{} {{", self.kind.name)?;
+        for (i, (field, value)) in
+            self.kind.fields.iter().zip(&self.values).enumerate()
+        {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            match value {
+                Object::String(x) => write!(f, " {}: {:?}", field, x)?,
+                _ => write!(f, " {}: {}", field, value)?,
+            }
+        }
+        write!(f, " }}")
+    }
+}
+
+#[derive(Debug)]
+pub struct EnumType {
+    pub name: String,
+    pub variants: Vec<(String, Vec<String>)>,
+    pub methods: RefCell<HashMap<String, Object>>,
+}
+
+#[derive(Debug)]
+pub struct Variant {
+    pub kind: Rc<EnumType>,
+    pub index: usize,
+    pub values: Vec<Object>,
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.kind.name, self.kind.variants[self.index].0)?;
+        if self.values.is_empty() {
+            return Ok(());
+        }
+        write!(f, "(")?;
+        for (i, value) in self.values.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match value {
+                Object::String(x) => write!(f, "{:?}", x)?,
+                _ => write!(f, "{}", value)?,
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+pub struct Function {
+    pub name: Option<String>,
+    pub params: Vec<AST>,
+    pub body: Vec<AST>,
+    pub env: Context,
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "<anonymous>"),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub enum Object {
+    Int(i64),
+    BigInt(BigInt),
+    Rational(BigRational),
     Float(f64),
+    Bool(bool),
     String(String),
-    Variable(String, Box<Context>),
-    Function(String, Vec<AST>, Vec<AST>),
+    Function(Rc<Function>),
+    Builtin(&'static str, usize, BuiltinFn),
+    Range(i64, i64),
+    List(Rc<RefCell<Vec<Object>>>),
+    Map(Rc<RefCell<Map>>),
+    StructType(Rc<StructType>),
+    Struct(Rc<RefCell<Instance>>),
+    EnumType(Rc<EnumType>),
+    Constructor(Rc<EnumType>, usize),
+    Variant(Rc<Variant>),
+    Null,
 }
 
-impl fmt::Display for Object {
+#[derive(Debug)]
+pub enum RuntimeError {
+    UndefinedVariable(String, Span),
+    UndeclaredAssignment(String, Span),
+    NotCallable(&'static str, Span),
+    ArityMismatch(String, usize, usize, Span),
+    InvalidOperands(String, &'static str, &'static str, Span),
+    InvalidOperand(String, &'static str, Span),
+    InvalidAssignment(Span),
+    TypeMismatch(&'static str, &'static str, Span),
+    NotIterable(&'static str, Span),
+    NotIndexable(&'static str, Span),
+    IndexOutOfRange(i64, usize, Span),
+    ItemAssignment(&'static str, Span),
+    MissingKey(String, Span),
+    InvalidKey(&'static str, Span),
+    UnknownField(String, String, Span),
+    MissingField(String, String, Span),
+    UnknownVariant(String, String, Span),
+    UnknownMethod(String, String, Span),
+    NoMatch(String, Span),
+    OutsideLoop(&'static str, Span),
+    OutsideFunction(Span),
+    Overflow(String, Span),
+    DivisionByZero(Span),
+}
+
+impl RuntimeError {
+    pub fn span(&self) -> Span {
+        match self {
+            RuntimeError::UndefinedVariable(_, span)
+            | RuntimeError::UndeclaredAssignment(_, span)
+            | RuntimeError::NotCallable(_, span)
+            | RuntimeError::ArityMismatch(_, _, _, span)
+            | RuntimeError::InvalidOperands(_, _, _, span)
+            | RuntimeError::InvalidOperand(_, _, span)
+            | RuntimeError::InvalidAssignment(span)
+            | RuntimeError::TypeMismatch(_, _, span)
+            | RuntimeError::NotIterable(_, span)
+            | RuntimeError::NotIndexable(_, span)
+            | RuntimeError::IndexOutOfRange(_, _, span)
+            | RuntimeError::ItemAssignment(_, span)
+            | RuntimeError::MissingKey(_, span)
+            | RuntimeError::InvalidKey(_, span)
+            | RuntimeError::UnknownField(_, _, span)
+            | RuntimeError::MissingField(_, _, span)
+            | RuntimeError::UnknownVariant(_, _, span)
+            | RuntimeError::UnknownMethod(_, _, span)
+            | RuntimeError::NoMatch(_, span)
+            | RuntimeError::OutsideLoop(_, span)
+            | RuntimeError::OutsideFunction(span)
+            | RuntimeError::Overflow(_, span)
+            | RuntimeError::DivisionByZero(span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Object::Float(x) => write!(f, "{}", x),
-            Object::Variable(x, context) => write!(
+            RuntimeError::UndefinedVariable(name, _) => {
+                write!(f, "Variable `{}` is not defined.", name)
+            }
+            RuntimeError::UndeclaredAssignment(name, _) => write!(
                 f,
-                "{} = {}",
-                x,
-                context.variables.get(x.as_str()).unwrap()
+                "Cannot assign to undeclared variable `{}`, \
+                 declare it with `let` first.",
+                name
             ),
+            RuntimeError::NotCallable(found, _) => {
+                write!(f, "{} is not callable.", found)
+            }
+            RuntimeError::ArityMismatch(name, expected, got, _) => write!(
+                f,
+                "Function `{}` takes {} argument(s) but {} were given.",
+                name, expected, got
+            ),
+            RuntimeError::InvalidOperands(op, lhs, rhs, _) => write!(
+                f,
+                "Operator `{}` is not supported for {} and {}.",
+                op, lhs, rhs
+            ),
+            RuntimeError::InvalidOperand(op, operand, _) => {
+                write!(f, "Operator `{}` is not supported for {}.", op, operand)
+            }
+            RuntimeError::InvalidAssignment(_) => {
+                write!(
+                    f,
+                    "Only variables, items and fields can be assigned to."
+                )
+            }
+            RuntimeError::TypeMismatch(expected, found, _) => {
+                write!(f, "Expected {}, found {}.", expected, found)
+            }
+            RuntimeError::NotIterable(found, _) => {
+                write!(f, "{} is not iterable.", found)
+            }
+            RuntimeError::NotIndexable(found, _) => {
+                write!(f, "{} cannot be indexed.", found)
+            }
+            RuntimeError::IndexOutOfRange(index, len, _) => {
+                write!(f, "Index {} is out of range for length {}.", index, len)
+            }
+            RuntimeError::ItemAssignment(found, _) => {
+                write!(f, "{} does not support item assignment.", found)
+            }
+            RuntimeError::MissingKey(key, _) => {
+                write!(f, "Key {} is not in the map.", key)
+            }
+            RuntimeError::InvalidKey(found, _) => {
+                write!(f, "{} cannot be used as a map key.", found)
+            }
+            RuntimeError::UnknownField(kind, field, _) => {
+                write!(f, "{} has no field `{}`.", kind, field)
+            }
+            RuntimeError::MissingField(kind, field, _) => {
+                write!(f, "Missing field `{}` in {} literal.", field, kind)
+            }
+            RuntimeError::UnknownVariant(kind, variant, _) => {
+                write!(f, "Enum {} has no variant `{}`.", kind, variant)
+            }
+            RuntimeError::UnknownMethod(kind, method, _) => {
+                write!(f, "{} has no method `{}`.", kind, method)
+            }
+            RuntimeError::NoMatch(value, _) => {
+                write!(f, "No `match` arm matches {}.", value)
+            }
+            RuntimeError::OutsideLoop(keyword, _) => {
+                write!(f, "`{}` outside of a loop.", keyword)
+            }
+            RuntimeError::OutsideFunction(_) => {
+                write!(f, "`return` outside of a function.")
+            }
+            RuntimeError::Overflow(op, _) => {
+                write!(f, "Result of `{}` is too large.", op)
+            }
+            RuntimeError::DivisionByZero(_) => write!(f, "Division by zero."),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl From<RuntimeError> for Diagnostic {
+    fn from(error: RuntimeError) -> Diagnostic {
+        Diagnostic::new(error.to_string(), error.span())
+    }
+}
+
+#[derive(Debug)]
+pub enum Flow {
+    Error(RuntimeError),
+    Break(Object, Span),
+    Continue(Span),
+    Return(Object, Span),
+}
+
+impl Flow {
+    fn into_error(self) -> RuntimeError {
+        match self {
+            Flow::Error(error) => error,
+            Flow::Break(_, span) => RuntimeError::OutsideLoop("break", span),
+            Flow::Continue(span) => RuntimeError::OutsideLoop("continue", span),
+            Flow::Return(_, span) => RuntimeError::OutsideFunction(span),
+        }
+    }
+}
+
+impl From<RuntimeError> for Flow {
+    fn from(error: RuntimeError) -> Flow {
+        Flow::Error(error)
+    }
+}
+
+// Operands of a numeric operation promoted to their narrowest common type.
+enum Numbers {
+    Int(i64, i64),
+    Big(BigInt, BigInt),
+    Rational(BigRational, BigRational),
+    Float(f64, f64),
+}
+
+impl Numbers {
+    fn cmp(&self) -> Option<Ordering> {
+        match self {
+            Numbers::Int(s, x) => Some(s.cmp(x)),
+            Numbers::Big(s, x) => Some(s.cmp(x)),
+            Numbers::Rational(s, x) => Some(s.cmp(x)),
+            Numbers::Float(s, x) => s.partial_cmp(x),
+        }
+    }
+}
+
+thread_local! {
+    static VISITING: RefCell<Vec<*const ()>> =
+        const { RefCell::new(Vec::new()) };
+}
+
+// Records that the containers at `s` and `x` are being compared, returning
+// `false` if they already were.
+fn enter(
+    seen: &mut Vec<(*const (), *const ())>,
+    s: *const (),
+    x: *const (),
+) -> bool {
+    if seen.contains(&(s, x)) {
+        return false;
+    }
+    seen.push((s, x));
+    true
+}
+
+// Runs `f` while `ptr` is marked as being displayed, or returns `None` if it
+// already is, so that cyclic lists, maps and structs print as `[...]`.
+fn visit<T>(ptr: *const (), f: impl FnOnce() -> T) -> Option<T> {
+    if VISITING.with(|visiting| visiting.borrow().contains(&ptr)) {
+        return None;
+    }
+    VISITING.with(|visiting| visiting.borrow_mut().push(ptr));
+    let result = f();
+    VISITING.with(|visiting| visiting.borrow_mut().pop());
+    Some(result)
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::Int(x) => write!(f, "{}", x),
+            Object::BigInt(x) => write!(f, "{}", x),
+            Object::Rational(x) => write!(f, "{}", x),
+            Object::Float(x) => write!(f, "{:?}", x),
+            Object::Bool(x) => write!(f, "{}", x),
             Object::String(x) => write!(f, "{}", x),
-            Object::Function(name, _, _) => write!(f, "Function {}", name),
+            Object::Function(function) => write!(f, "Function {}", function),
+            Object::Builtin(name, _, _) => write!(f, "Builtin {}", name),
+            Object::Range(start, end) => write!(f, "{}..{}", start, end),
+            Object::List(list) => visit(Rc::as_ptr(list) as _, || {
+                write!(f, "[")?;
+                for (i, item) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match item {
+                        Object::String(x) => write!(f, "{:?}", x)?,
+                        _ => write!(f, "{}", item)?,
+                    }
+                }
+                write!(f, "]")
+            })
+            .unwrap_or_else(|| write!(f, "[...]")),
+            Object::Map(map) => visit(Rc::as_ptr(map) as _, || {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match value {
+                        Object::String(x) => write!(f, "{}: {:?}", key, x)?,
+                        _ => write!(f, "{}: {}", key, value)?,
+                    }
+                }
+                write!(f, "}}")
+            })
+            .unwrap_or_else(|| write!(f, "{{...}}")),
+            Object::StructType(kind) => write!(f, "Struct {}", kind.name),
+            Object::Struct(instance) => {
+                visit(Rc::as_ptr(instance) as _, || {
+                    write!(f, "{}", instance.borrow())
+                })
+                .unwrap_or_else(|| {
+                    write!(f, "{} {{...}}", instance.borrow().kind.name)
+                })
+            }
+            Object::EnumType(kind) => write!(f, "Enum {}", kind.name),
+            Object::Constructor(kind, index) => {
+                write!(f, "Function {}.{}", kind.name, kind.variants[*index].0)
+            }
+            Object::Variant(variant) => write!(f, "{}", variant),
+            Object::Null => write!(f, "null"),
         }
     }
 }
 
 impl Object {
-    fn add(&self, other: &Self) -> Object {
-        if mem::discriminant(self) == mem::discriminant(other) {
-            match (self, other) {
-                (Object::Float(s), Object::Float(x)) => {
-                    return Object::Float(s + x)
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Int(_) | Object::BigInt(_) => "Int",
+            Object::Rational(_) => "Rational",
+            Object::Float(_) => "Float",
+            Object::Bool(_) => "Bool",
+            Object::String(_) => "String",
+            Object::Function(_) | Object::Builtin(_, _, _) => "Function",
+            Object::Range(_, _) => "Range",
+            Object::List(_) => "List",
+            Object::Map(_) => "Map",
+            Object::StructType(_) => "Struct type",
+            Object::Struct(_) => "Struct",
+            Object::EnumType(_) => "Enum type",
+            Object::Constructor(_, _) => "Function",
+            Object::Variant(_) => "Enum",
+            Object::Null => "Null",
+        }
+    }
+
+    fn invalid_operands(
+        &self,
+        op: &str,
+        other: &Self,
+        span: Span,
+    ) -> RuntimeError {
+        RuntimeError::InvalidOperands(
+            op.to_string(),
+            self.type_name(),
+            other.type_name(),
+            span,
+        )
+    }
+
+    fn as_bool(&self, span: Span) -> Result<bool, RuntimeError> {
+        match self {
+            Object::Bool(x) => Ok(*x),
+            _ => {
+                Err(RuntimeError::TypeMismatch("Bool", self.type_name(), span))
+            }
+        }
+    }
+
+    pub fn equals(&self, other: &Self) -> bool {
+        self.equals_in(other, &mut Vec::new())
+    }
+
+    // Structural equality that assumes a pair of containers already being
+    // compared is equal, so that cyclic values terminate.
+    fn equals_in(
+        &self,
+        other: &Self,
+        seen: &mut Vec<(*const (), *const ())>,
+    ) -> bool {
+        if let Some(numbers) = self.numbers(other) {
+            return numbers.cmp() == Some(Ordering::Equal);
+        }
+        match (self, other) {
+            (Object::Bool(s), Object::Bool(x)) => s == x,
+            (Object::String(s), Object::String(x)) => s == x,
+            (Object::List(s), Object::List(x)) => {
+                if !enter(seen, Rc::as_ptr(s) as _, Rc::as_ptr(x) as _) {
+                    return true;
                 }
-                _ => todo!(
-                    "Operation ADD not implemented for {:?} and {:?}",
-                    self,
-                    other
-                ),
+                let (s, x) = (s.borrow(), x.borrow());
+                s.len() == x.len()
+                    && s.iter().zip(x.iter()).all(|(s, x)| s.equals_in(x, seen))
+            }
+            (Object::Map(s), Object::Map(x)) => {
+                if !enter(seen, Rc::as_ptr(s) as _, Rc::as_ptr(x) as _) {
+                    return true;
+                }
+                let (s, x) = (s.borrow(), x.borrow());
+                s.len() == x.len()
+                    && s.iter().all(|(key, s)| {
+                        x.get(key).is_some_and(|x| s.equals_in(x, seen))
+                    })
+            }
+            (Object::StructType(s), Object::StructType(x)) => Rc::ptr_eq(s, x),
+            (Object::Struct(s), Object::Struct(x)) => {
+                if !enter(seen, Rc::as_ptr(s) as _, Rc::as_ptr(x) as _) {
+                    return true;
+                }
+                let (s, x) = (s.borrow(), x.borrow());
+                Rc::ptr_eq(&s.kind, &x.kind)
+                    && s.values
+                        .iter()
+                        .zip(&x.values)
+                        .all(|(s, x)| s.equals_in(x, seen))
+            }
+            (Object::EnumType(s), Object::EnumType(x)) => Rc::ptr_eq(s, x),
+            (Object::Constructor(s, i), Object::Constructor(x, j)) => {
+                Rc::ptr_eq(s, x) && i == j
+            }
+            (Object::Variant(s), Object::Variant(x)) => {
+                Rc::ptr_eq(&s.kind, &x.kind)
+                    && s.index == x.index
+                    && s.values
+                        .iter()
+                        .zip(&x.values)
+                        .all(|(s, x)| s.equals_in(x, seen))
             }
+            (Object::Function(s), Object::Function(x)) => Rc::ptr_eq(s, x),
+            (Object::Builtin(s, _, _), Object::Builtin(x, _, _)) => s == x,
+            (Object::Null, Object::Null) => true,
+            _ => false,
         }
-        todo!(
-            "Operation ADD not implemented for {:?} and {:?}",
+    }
+
+    fn compare(
+        &self,
+        op: &str,
+        other: &Self,
+        span: Span,
+    ) -> Result<Object, RuntimeError> {
+        let ordering = match (self.numbers(other), self, other) {
+            (Some(numbers), _, _) => numbers.cmp(),
+            (None, Object::String(s), Object::String(x)) => Some(s.cmp(x)),
+            _ => return Err(self.invalid_operands(op, other, span)),
+        };
+        Ok(Object::Bool(match (op, ordering) {
+            (_, None) => false,
+            ("<", Some(ordering)) => ordering == Ordering::Less,
+            ("<=", Some(ordering)) => ordering != Ordering::Greater,
+            (">", Some(ordering)) => ordering == Ordering::Greater,
+            (">=", Some(ordering)) => ordering != Ordering::Less,
+            _ => return Err(self.invalid_operands(op, other, span)),
+        }))
+    }
+
+    pub fn from_bigint(n: BigInt) -> Object {
+        match n.to_i64() {
+            Some(n) => Object::Int(n),
+            None => Object::BigInt(n),
+        }
+    }
+
+    pub fn from_rational(n: BigRational) -> Object {
+        if n.is_integer() {
+            Object::from_bigint(n.to_integer())
+        } else {
+            Object::Rational(n)
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        matches!(
             self,
-            other
+            Object::Int(_) | Object::BigInt(_) | Object::Rational(_)
         )
     }
 
-    fn neg(&self) -> Object {
+    pub fn to_bigint(&self) -> Option<BigInt> {
+        match self {
+            Object::Int(x) => Some(BigInt::from(*x)),
+            Object::BigInt(x) => Some(x.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn to_rational(&self) -> Option<BigRational> {
         match self {
-            Object::Float(x) => Object::Float(-x),
-            _ => todo!("Operation NEG not implemented for {:?}", self),
+            Object::Rational(x) => Some(x.clone()),
+            _ => Some(BigRational::from_integer(self.to_bigint()?)),
         }
     }
 
-    fn mult(&self, other: &Self) -> Object {
-        if mem::discriminant(self) == mem::discriminant(other) {
-            match (self, other) {
-                (Object::Float(s), Object::Float(x)) => {
-                    return Object::Float(s * x)
+    pub fn to_f64(&self) -> Option<f64> {
+        match self {
+            Object::Int(x) => Some(*x as f64),
+            Object::BigInt(x) => x.to_f64(),
+            Object::Rational(x) => x.to_f64(),
+            Object::Float(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    fn numbers(&self, other: &Self) -> Option<Numbers> {
+        Some(match (self, other) {
+            (Object::Int(s), Object::Int(x)) => Numbers::Int(*s, *x),
+            (Object::Float(_), _) | (_, Object::Float(_)) => {
+                Numbers::Float(self.to_f64()?, other.to_f64()?)
+            }
+            (Object::Rational(_), _) | (_, Object::Rational(_)) => {
+                Numbers::Rational(self.to_rational()?, other.to_rational()?)
+            }
+            _ => Numbers::Big(self.to_bigint()?, other.to_bigint()?),
+        })
+    }
+
+    fn iterate(
+        &self,
+        span: Span,
+    ) -> Result<Box<dyn Iterator<Item = Object>>, RuntimeError> {
+        match self {
+            Object::Range(start, end) => {
+                Ok(Box::new((*start..*end).map(Object::Int)))
+            }
+            Object::String(string) => Ok(Box::new(
+                string
+                    .chars()
+                    .map(|c| Object::String(c.to_string()))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            )),
+            Object::List(list) => {
+                Ok(Box::new(list.borrow().clone().into_iter()))
+            }
+            Object::Map(map) => Ok(Box::new(
+                map.borrow()
+                    .iter()
+                    .map(|(key, _)| key.to_object())
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            )),
+            _ => Err(RuntimeError::NotIterable(self.type_name(), span)),
+        }
+    }
+
+    fn range(&self, other: &Self, span: Span) -> Result<Object, RuntimeError> {
+        match (self, other) {
+            (Object::Int(s), Object::Int(x)) => Ok(Object::Range(*s, *x)),
+            _ => Err(self.invalid_operands("..", other, span)),
+        }
+    }
+
+    pub fn as_index(&self, span: Span) -> Result<i64, RuntimeError> {
+        match self {
+            Object::Int(x) => Ok(*x),
+            // Too large to be in range either way, saturate and let the
+            // bounds checks reject or clamp it.
+            Object::BigInt(x) if x.is_negative() => Ok(i64::MIN),
+            Object::BigInt(_) => Ok(i64::MAX),
+            _ => Err(RuntimeError::TypeMismatch("Int", self.type_name(), span)),
+        }
+    }
+
+    pub fn as_key(&self, span: Span) -> Result<Key, RuntimeError> {
+        match self {
+            Object::Int(_) | Object::BigInt(_) => {
+                Ok(Key::Int(self.to_bigint().unwrap_or_default()))
+            }
+            Object::String(x) => Ok(Key::String(x.clone())),
+            Object::Bool(x) => Ok(Key::Bool(*x)),
+            _ => Err(RuntimeError::InvalidKey(self.type_name(), span)),
+        }
+    }
+
+    fn index(&self, index: &Self, span: Span) -> Result<Object, RuntimeError> {
+        match self {
+            Object::Map(map) => {
+                let key = index.as_key(span)?;
+                match map.borrow().get(&key) {
+                    Some(value) => Ok(value.clone()),
+                    None => {
+                        Err(RuntimeError::MissingKey(key.to_string(), span))
+                    }
+                }
+            }
+            Object::String(string) => {
+                let index = index.as_index(span)?;
+                let len = string.chars().count();
+                match position(index, len).and_then(|i| string.chars().nth(i)) {
+                    Some(c) => Ok(Object::String(c.to_string())),
+                    None => {
+                        Err(RuntimeError::IndexOutOfRange(index, len, span))
+                    }
+                }
+            }
+            Object::List(list) => {
+                let index = index.as_index(span)?;
+                let list = list.borrow();
+                match position(index, list.len()) {
+                    Some(i) => Ok(list[i].clone()),
+                    None => Err(RuntimeError::IndexOutOfRange(
+                        index,
+                        list.len(),
+                        span,
+                    )),
                 }
-                _ => todo!(
-                    "Operation MULT not implemented for {:?} and {:?}",
-                    self,
-                    other
-                ),
             }
+            _ => Err(RuntimeError::NotIndexable(self.type_name(), span)),
         }
-        todo!(
-            "Operation MULT not implemented for {:?} and {:?}",
-            self,
-            other
-        )
     }
 
-    fn div(&self, other: &Self) -> Object {
-        if mem::discriminant(self) == mem::discriminant(other) {
-            match (self, other) {
-                (Object::Float(s), Object::Float(x)) => {
-                    return Object::Float(s / x)
+    fn set_index(
+        &self,
+        index: &Self,
+        value: Object,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        let list = match self {
+            Object::List(list) => list,
+            Object::Map(map) => {
+                map.borrow_mut().insert(index.as_key(span)?, value);
+                return Ok(());
+            }
+            _ => {
+                return Err(RuntimeError::ItemAssignment(
+                    self.type_name(),
+                    span,
+                ))
+            }
+        };
+        let index = index.as_index(span)?;
+        let mut list = list.borrow_mut();
+        let len = list.len();
+        match position(index, len) {
+            Some(i) => list[i] = value,
+            None => {
+                return Err(RuntimeError::IndexOutOfRange(index, len, span))
+            }
+        }
+        Ok(())
+    }
+
+    fn field(&self, field: &str, span: Span) -> Result<Object, RuntimeError> {
+        match self {
+            Object::EnumType(kind) => {
+                let Some(index) =
+                    kind.variants.iter().position(|(name, _)| name == field)
+                else {
+                    return Err(RuntimeError::UnknownVariant(
+                        kind.name.clone(),
+                        field.to_string(),
+                        span,
+                    ));
+                };
+                // Variants without fields are values, the others construct
+                // one when called, `Shape.Circle(1)`.
+                if kind.variants[index].1.is_empty() {
+                    return Ok(Object::Variant(Rc::new(Variant {
+                        kind: kind.clone(),
+                        index,
+                        values: vec![],
+                    })));
                 }
-                _ => todo!(
-                    "Operation DIV not implemented for {:?} and {:?}",
-                    self,
-                    other
-                ),
+                return Ok(Object::Constructor(kind.clone(), index));
+            }
+            Object::Variant(variant) => {
+                let (name, fields) = &variant.kind.variants[variant.index];
+                return match fields.iter().position(|name| name == field) {
+                    Some(i) => Ok(variant.values[i].clone()),
+                    None => Err(RuntimeError::UnknownField(
+                        format!("{}.{}", variant.kind.name, name),
+                        field.to_string(),
+                        span,
+                    )),
+                };
             }
+            _ => {}
+        }
+        let Object::Struct(instance) = self else {
+            return Err(RuntimeError::UnknownField(
+                self.type_name().to_string(),
+                field.to_string(),
+                span,
+            ));
+        };
+        let instance = instance.borrow();
+        match instance.kind.fields.iter().position(|name| name == field) {
+            Some(i) => Ok(instance.values[i].clone()),
+            None => Err(RuntimeError::UnknownField(
+                instance.kind.name.clone(),
+                field.to_string(),
+                span,
+            )),
+        }
+    }
+
+    fn set_field(
+        &self,
+        field: &str,
+        value: Object,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        let Object::Struct(instance) = self else {
+            return Err(RuntimeError::UnknownField(
+                self.type_name().to_string(),
+                field.to_string(),
+                span,
+            ));
+        };
+        let mut instance = instance.borrow_mut();
+        match instance.kind.fields.iter().position(|name| name == field) {
+            Some(i) => instance.values[i] = value,
+            None => {
+                return Err(RuntimeError::UnknownField(
+                    instance.kind.name.clone(),
+                    field.to_string(),
+                    span,
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn slice(
+        &self,
+        start: Option<Object>,
+        end: Option<Object>,
+        span: Span,
+    ) -> Result<Object, RuntimeError> {
+        let len = match self {
+            Object::String(string) => string.chars().count(),
+            Object::List(list) => list.borrow().len(),
+            _ => {
+                return Err(RuntimeError::NotIndexable(self.type_name(), span))
+            }
+        };
+        let bound = |bound: Option<Object>, default: usize| match bound {
+            None => Ok(default),
+            Some(bound) => {
+                let bound = bound.as_index(span)?;
+                Ok(if bound < 0 {
+                    len.saturating_sub(bound.unsigned_abs() as usize)
+                } else {
+                    len.min(bound as usize)
+                })
+            }
+        };
+        let start = bound(start, 0)?;
+        let count = bound(end, len)?.saturating_sub(start);
+        Ok(match self {
+            Object::List(list) => Object::List(Rc::new(RefCell::new(
+                list.borrow()
+                    .iter()
+                    .skip(start)
+                    .take(count)
+                    .cloned()
+                    .collect(),
+            ))),
+            Object::String(string) => {
+                Object::String(string.chars().skip(start).take(count).collect())
+            }
+            _ => unreachable!("Only strings and lists can be sliced."),
+        })
+    }
+
+    // Methods defined in an `impl` block take precedence over the built-in
+    // methods of a value.
+    fn method(&self, name: &str) -> Option<Object> {
+        let methods = match self {
+            Object::Struct(instance) => {
+                instance.borrow().kind.methods.borrow().get(name).cloned()
+            }
+            Object::Variant(variant) => {
+                variant.kind.methods.borrow().get(name).cloned()
+            }
+            _ => None,
+        };
+        methods.or_else(|| {
+            let (name, arity, function) = builtins::method(self, name)?;
+            Some(Object::Builtin(name, arity, function))
+        })
+    }
+
+    fn signature(&self) -> Option<(String, usize)> {
+        match self {
+            Object::Function(function) => {
+                Some((function.to_string(), function.params.len()))
+            }
+            Object::Builtin(name, arity, _) => Some((name.to_string(), *arity)),
+            Object::Constructor(kind, index) => {
+                let (name, fields) = &kind.variants[*index];
+                Some((format!("{}.{}", kind.name, name), fields.len()))
+            }
+            _ => None,
         }
-        todo!(
-            "Operation DIV not implemented for {:?} and {:?}",
-            self,
-            other
-        )
     }
 
-    fn modu(&self, other: &Self) -> Object {
-        if mem::discriminant(self) == mem::discriminant(other) {
-            match (self, other) {
-                (Object::Float(s), Object::Float(x)) => {
-                    return Object::Float(s % x)
+    pub fn call(
+        &self,
+        args: Vec<Object>,
+        span: Span,
+    ) -> Result<Object, RuntimeError> {
+        let function = match self {
+            Object::Function(function) => function,
+            Object::Builtin(name, arity, function) => {
+                if *arity != args.len() {
+                    return Err(RuntimeError::ArityMismatch(
+                        name.to_string(),
+                        *arity,
+                        args.len(),
+                        span,
+                    ));
                 }
-                _ => todo!(
-                    "Operation MODU not implemented for {:?} and {:?}",
-                    self,
-                    other
-                ),
+                return function(&args, span);
             }
+            Object::Constructor(kind, index) => {
+                let (name, fields) = &kind.variants[*index];
+                if fields.len() != args.len() {
+                    return Err(RuntimeError::ArityMismatch(
+                        format!("{}.{}", kind.name, name),
+                        fields.len(),
+                        args.len(),
+                        span,
+                    ));
+                }
+                return Ok(Object::Variant(Rc::new(Variant {
+                    kind: kind.clone(),
+                    index: *index,
+                    values: args,
+                })));
+            }
+            _ => return Err(RuntimeError::NotCallable(self.type_name(), span)),
+        };
+        if function.params.len() != args.len() {
+            return Err(RuntimeError::ArityMismatch(
+                function.to_string(),
+                function.params.len(),
+                args.len(),
+                span,
+            ));
         }
-        todo!(
-            "Operation MODU not implemented for {:?} and {:?}",
-            self,
-            other
+        let mut scope = function.env.child();
+        for (param, value) in function.params.iter().zip(args) {
+            if let AST::Variable(param, _) = param {
+                scope.declare(param, value);
+            }
+        }
+        match run_block(&function.body, &mut scope) {
+            Ok(value) | Err(Flow::Return(value, _)) => Ok(value),
+            Err(flow) => Err(flow.into_error()),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn arithmetic(
+        &self,
+        op: &str,
+        other: &Self,
+        span: Span,
+        int: fn(i64, i64) -> Option<i64>,
+        big: fn(BigInt, BigInt) -> BigInt,
+        rational: fn(BigRational, BigRational) -> BigRational,
+        float: fn(f64, f64) -> f64,
+    ) -> Result<Object, RuntimeError> {
+        let Some(numbers) = self.numbers(other) else {
+            return Err(self.invalid_operands(op, other, span));
+        };
+        Ok(match numbers {
+            Numbers::Int(s, x) => match int(s, x) {
+                Some(result) => Object::Int(result),
+                None => Object::from_bigint(big(s.into(), x.into())),
+            },
+            Numbers::Big(s, x) => Object::from_bigint(big(s, x)),
+            Numbers::Rational(s, x) => Object::from_rational(rational(s, x)),
+            Numbers::Float(s, x) => Object::Float(float(s, x)),
+        })
+    }
+
+    fn add(&self, other: &Self, span: Span) -> Result<Object, RuntimeError> {
+        if let (Object::String(s), Object::String(x)) = (self, other) {
+            return Ok(Object::String(format!("{}{}", s, x)));
+        }
+        self.arithmetic(
+            "+",
+            other,
+            span,
+            i64::checked_add,
+            |s, x| s + x,
+            |s, x| s + x,
+            |s, x| s + x,
+        )
+    }
+
+    fn sub(&self, other: &Self, span: Span) -> Result<Object, RuntimeError> {
+        self.arithmetic(
+            "-",
+            other,
+            span,
+            i64::checked_sub,
+            |s, x| s - x,
+            |s, x| s - x,
+            |s, x| s - x,
         )
     }
 
-    fn pow(&self, other: &Self) -> Object {
-        if mem::discriminant(self) == mem::discriminant(other) {
-            match (self, other) {
-                (Object::Float(s), Object::Float(x)) => {
-                    return Object::Float(s.powf(*x))
+    fn not(&self, span: Span) -> Result<Object, RuntimeError> {
+        match self {
+            Object::Bool(x) => Ok(Object::Bool(!x)),
+            _ => Err(RuntimeError::InvalidOperand(
+                "!".to_string(),
+                self.type_name(),
+                span,
+            )),
+        }
+    }
+
+    fn neg(&self, span: Span) -> Result<Object, RuntimeError> {
+        match self {
+            Object::Int(x) => Ok(match x.checked_neg() {
+                Some(x) => Object::Int(x),
+                None => Object::from_bigint(-BigInt::from(*x)),
+            }),
+            Object::BigInt(x) => Ok(Object::from_bigint(-x)),
+            Object::Rational(x) => Ok(Object::Rational(-x)),
+            Object::Float(x) => Ok(Object::Float(-x)),
+            _ => Err(RuntimeError::InvalidOperand(
+                "-".to_string(),
+                self.type_name(),
+                span,
+            )),
+        }
+    }
+
+    fn mult(&self, other: &Self, span: Span) -> Result<Object, RuntimeError> {
+        match (self, other) {
+            (Object::String(s), Object::Int(x))
+            | (Object::Int(x), Object::String(s)) => {
+                let count = usize::try_from(*x).unwrap_or(0);
+                let overflow = || RuntimeError::Overflow("*".to_string(), span);
+                let size = s.len().checked_mul(count).ok_or_else(overflow)?;
+                let mut string = String::new();
+                string.try_reserve(size).map_err(|_| overflow())?;
+                for _ in 0..count {
+                    string.push_str(s);
                 }
-                _ => todo!(
-                    "Operation POW not implemented for {:?} and {:?}",
-                    self,
-                    other
-                ),
+                return Ok(Object::String(string));
             }
+            _ => {}
         }
-        todo!(
-            "Operation POW not implemented for {:?} and {:?}",
-            self,
-            other
+        self.arithmetic(
+            "*",
+            other,
+            span,
+            i64::checked_mul,
+            |s, x| s * x,
+            |s, x| s * x,
+            |s, x| s * x,
+        )
+    }
+
+    fn div(&self, other: &Self, span: Span) -> Result<Object, RuntimeError> {
+        let Some(numbers) = self.numbers(other) else {
+            return Err(self.invalid_operands("/", other, span));
+        };
+        // Dividing exact numbers stays exact, `1 / 3` is the rational 1/3.
+        Ok(match numbers {
+            Numbers::Float(s, x) => Object::Float(s / x),
+            _ if matches!(other, Object::Int(0)) => {
+                return Err(RuntimeError::DivisionByZero(span))
+            }
+            Numbers::Int(s, x) if s.checked_rem(x) == Some(0) => {
+                Object::Int(s / x)
+            }
+            Numbers::Int(s, x) => {
+                Object::from_rational(BigRational::new(s.into(), x.into()))
+            }
+            Numbers::Big(s, x) => Object::from_rational(BigRational::new(s, x)),
+            Numbers::Rational(s, x) => Object::from_rational(s / x),
+        })
+    }
+
+    fn modu(&self, other: &Self, span: Span) -> Result<Object, RuntimeError> {
+        if self.is_exact() && matches!(other, Object::Int(0)) {
+            return Err(RuntimeError::DivisionByZero(span));
+        }
+        self.arithmetic(
+            "%",
+            other,
+            span,
+            i64::checked_rem,
+            |s, x| s % x,
+            |s, x| s % x,
+            |s, x| s % x,
         )
     }
+
+    fn pow(&self, other: &Self, span: Span) -> Result<Object, RuntimeError> {
+        if let (Object::Int(s), Object::Int(x)) = (self, other) {
+            if let Some(result) =
+                u32::try_from(*x).ok().and_then(|x| s.checked_pow(x))
+            {
+                return Ok(Object::Int(result));
+            }
+        }
+        if let (true, Object::Int(x)) = (self.is_exact(), other) {
+            let Ok(exponent) = u32::try_from(x.unsigned_abs()) else {
+                return Err(RuntimeError::Overflow("**".to_string(), span));
+            };
+            let base = self.to_rational().unwrap_or_default();
+            let bits = base.numer().bits().saturating_sub(1)
+                + base.denom().bits().saturating_sub(1);
+            if bits.saturating_mul(exponent.into()) > MAX_POW_BITS {
+                return Err(RuntimeError::Overflow("**".to_string(), span));
+            }
+            let result = BigRational::new(
+                base.numer().pow(exponent),
+                base.denom().pow(exponent),
+            );
+            if *x >= 0 {
+                return Ok(Object::from_rational(result));
+            }
+            if result.is_zero() {
+                return Err(RuntimeError::DivisionByZero(span));
+            }
+            return Ok(Object::from_rational(result.recip()));
+        }
+        match (self.to_f64(), other.to_f64()) {
+            (Some(s), Some(x)) => Ok(Object::Float(s.powf(x))),
+            _ => Err(self.invalid_operands("**", other, span)),
+        }
+    }
+}
+
+// Resolves an index into a sequence of `len` items, negative indices count
+// from the end so `xs[-1]` is the last item.
+pub fn position(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 {
+        index.checked_add(len.try_into().ok()?)?
+    } else {
+        index
+    };
+    usize::try_from(index).ok().filter(|&i| i < len)
 }
 
 trait Run {
-    fn execute(&self, context: &mut Context) -> Object;
+    fn execute(&self, context: &mut Context) -> Result<Object, Flow>;
 }
 
 impl Run for AST {
-    fn execute(&self, context: &mut Context) -> Object {
-        match self {
-            AST::Number(num) => Object::Float(*num),
-            AST::BinaryOp(op, lhs, rhs) => {
+    fn execute(&self, context: &mut Context) -> Result<Object, Flow> {
+        Ok(match self {
+            AST::Int(num, _) => Object::Int(*num),
+            AST::BigInt(num, _) => Object::BigInt(num.clone()),
+            AST::Number(num, _) => Object::Float(*num),
+            AST::Bool(value, _) => Object::Bool(*value),
+            AST::BinaryOp(op, lhs, rhs, span) if op == "&&" || op == "||" => {
+                let value = lhs.execute(context)?.as_bool(lhs.span())?;
+                if value == (op == "||") {
+                    return Ok(Object::Bool(value));
+                }
+                Object::Bool(rhs.execute(context)?.as_bool(rhs.span())?)
+            }
+            AST::BinaryOp(op, lhs, rhs, span) => {
+                if op == "=" {
+                    return match &**lhs {
+                        AST::Variable(name, _) => {
+                            let value = rhs.execute(context)?;
+                            if !context.assign(name, value.clone()) {
+                                return Err(
+                                    RuntimeError::UndeclaredAssignment(
+                                        name.clone(),
+                                        *span,
+                                    )
+                                    .into(),
+                                );
+                            }
+                            Ok(value)
+                        }
+                        AST::Index(target, index, _) => {
+                            let target = target.execute(context)?;
+                            let index = index.execute(context)?;
+                            let value = rhs.execute(context)?;
+                            target.set_index(&index, value.clone(), *span)?;
+                            Ok(value)
+                        }
+                        AST::Field(target, field, _) => {
+                            let target = target.execute(context)?;
+                            let value = rhs.execute(context)?;
+                            target.set_field(field, value.clone(), *span)?;
+                            Ok(value)
+                        }
+                        _ => Err(RuntimeError::InvalidAssignment(*span).into()),
+                    };
+                }
+                let lhs = lhs.execute(context)?;
+                let rhs = rhs.execute(context)?;
                 match op.as_str() {
-                    "+" => lhs.execute(context).add(&rhs.execute(context)),
-                    "-" => {
-                        lhs.execute(context).add(&rhs.execute(context).neg())
-                    }
-                    "*" => lhs.execute(context).mult(&rhs.execute(context)),
-                    "/" => lhs.execute(context).div(&rhs.execute(context)),
-                    "%" => lhs.execute(context).modu(&rhs.execute(context)),
-                    "**" => lhs.execute(context).pow(&rhs.execute(context)),
-                    "=" => {
-                        let name;
-                        // println!("This");
-                        let mut cntx = context.clone();
-                        context.variables.insert(
-                            match &**lhs {
-                                AST::Variable(s) => {
-                                    name = s;
-                                    s.clone()
-                                }
-                                _ => panic!(),
-                            },
-                            rhs.execute(&mut cntx),
-                        );
-                        // dbg!(name, &context.variables);
-                        // println!("And This");
-                        Object::Variable(
-                            name.clone(),
-                            Box::new(context.clone()),
+                    "+" => lhs.add(&rhs, *span)?,
+                    "-" => lhs.sub(&rhs, *span)?,
+                    "*" => lhs.mult(&rhs, *span)?,
+                    "/" => lhs.div(&rhs, *span)?,
+                    "%" => lhs.modu(&rhs, *span)?,
+                    "**" => lhs.pow(&rhs, *span)?,
+                    ".." => lhs.range(&rhs, *span)?,
+                    "==" => Object::Bool(lhs.equals(&rhs)),
+                    "!=" => Object::Bool(!lhs.equals(&rhs)),
+                    "<" | "<=" | ">" | ">=" => lhs.compare(op, &rhs, *span)?,
+                    _ => {
+                        return Err(lhs
+                            .invalid_operands(op, &rhs, *span)
+                            .into())
+                    }
+                }
+            }
+            AST::UnaryOp(op, exp, span) => {
+                let value = exp.execute(context)?;
+                match op.as_str() {
+                    "+" => value,
+                    "-" => value.neg(*span)?,
+                    "!" => value.not(*span)?,
+                    _ => {
+                        return Err(RuntimeError::InvalidOperand(
+                            op.clone(),
+                            value.type_name(),
+                            *span,
                         )
+                        .into())
                     }
-                    _ => todo!(),
                 }
             }
-            AST::UnaryOp(op, exp) => match op.as_str() {
-                "+" => exp.execute(context),
-                "-" => exp.execute(context).neg(),
-                default => {
-                    todo!("No implementation for operator `{}`.", default)
+            AST::Variable(name, span) => match context.get(name) {
+                Some(value) => value,
+                None => {
+                    return Err(RuntimeError::UndefinedVariable(
+                        name.clone(),
+                        *span,
+                    )
+                    .into())
                 }
             },
-            AST::Variable(name) => {
-                // dbg!(name, VARIABLES.lock().unwrap());
-                return context.variables.get(name.as_str()).unwrap().clone();
+            AST::Let(name, value, _) => {
+                let value = value.execute(context)?;
+                context.declare(name, value.clone());
+                value
             }
-            AST::String(string) => Object::String(string.clone()),
-            AST::Function(name, variables, code) => {
-                Object::Function(name.clone(), variables.clone(), code.clone())
+            AST::String(string, _) => Object::String(string.clone()),
+            AST::List(items, _) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    values.push(item.execute(context)?);
+                }
+                Object::List(Rc::new(RefCell::new(values)))
             }
-            default => todo!("No implementation for {:?}", default),
-        }
+            AST::Map(entries, _) => {
+                let mut map = Map::default();
+                for (key, value) in entries {
+                    let key = key.execute(context)?.as_key(key.span())?;
+                    map.insert(key, value.execute(context)?);
+                }
+                Object::Map(Rc::new(RefCell::new(map)))
+            }
+            AST::Struct(name, fields, _) => {
+                let kind = Object::StructType(Rc::new(StructType {
+                    name: name.clone(),
+                    fields: fields.clone(),
+                    methods: RefCell::default(),
+                }));
+                context.declare(name, kind.clone());
+                kind
+            }
+            AST::StructLiteral(name, fields, span) => {
+                let kind = match context.get(name) {
+                    Some(Object::StructType(kind)) => kind,
+                    Some(value) => {
+                        return Err(RuntimeError::TypeMismatch(
+                            "Struct type",
+                            value.type_name(),
+                            *span,
+                        )
+                        .into())
+                    }
+                    None => {
+                        return Err(RuntimeError::UndefinedVariable(
+                            name.clone(),
+                            *span,
+                        )
+                        .into())
+                    }
+                };
+                let mut values = vec![None; kind.fields.len()];
+                for (field, value) in fields {
+                    let Some(i) = kind.fields.iter().position(|x| x == field)
+                    else {
+                        return Err(RuntimeError::UnknownField(
+                            name.clone(),
+                            field.clone(),
+                            value.span(),
+                        )
+                        .into());
+                    };
+                    values[i] = Some(value.execute(context)?);
+                }
+                let mut missing = kind.fields.iter().zip(&values);
+                if let Some((field, _)) = missing.find(|(_, x)| x.is_none()) {
+                    return Err(RuntimeError::MissingField(
+                        name.clone(),
+                        field.clone(),
+                        *span,
+                    )
+                    .into());
+                }
+                Object::Struct(Rc::new(RefCell::new(Instance {
+                    kind,
+                    values: values.into_iter().flatten().collect(),
+                })))
+            }
+            AST::Field(target, field, span) => {
+                target.execute(context)?.field(field, *span)?
+            }
+            AST::Enum(name, variants, _) => {
+                let kind = Object::EnumType(Rc::new(EnumType {
+                    name: name.clone(),
+                    variants: variants.clone(),
+                    methods: RefCell::default(),
+                }));
+                context.declare(name, kind.clone());
+                kind
+            }
+            AST::Impl(name, methods, span) => {
+                let kind = match context.get(name) {
+                    Some(
+                        kind @ (Object::StructType(_) | Object::EnumType(_)),
+                    ) => kind,
+                    Some(value) => {
+                        return Err(RuntimeError::TypeMismatch(
+                            "Struct or Enum type",
+                            value.type_name(),
+                            *span,
+                        )
+                        .into())
+                    }
+                    None => {
+                        return Err(RuntimeError::UndefinedVariable(
+                            name.clone(),
+                            *span,
+                        )
+                        .into())
+                    }
+                };
+                let table = match &kind {
+                    Object::StructType(kind) => &kind.methods,
+                    Object::EnumType(kind) => &kind.methods,
+                    _ => unreachable!("Only types were accepted above."),
+                };
+                for method in methods {
+                    if let AST::Function(method, params, body, _) = method {
+                        let function = Object::Function(Rc::new(Function {
+                            name: Some(method.clone()),
+                            params: params.clone(),
+                            body: body.clone(),
+                            env: context.clone(),
+                        }));
+                        table.borrow_mut().insert(method.clone(), function);
+                    }
+                }
+                kind
+            }
+            AST::Match(subject, arms, span) => {
+                let value = subject.execute(context)?;
+                for (pattern, guard, body) in arms {
+                    let mut scope = context.child();
+                    if !pattern.bind(&value, &mut scope)? {
+                        continue;
+                    }
+                    if let Some(guard) = guard {
+                        if !guard.execute(&mut scope)?.as_bool(guard.span())? {
+                            continue;
+                        }
+                    }
+                    return run_block(body, &mut scope);
+                }
+                return Err(
+                    RuntimeError::NoMatch(value.to_string(), *span).into()
+                );
+            }
+            AST::Interpolation(pieces, _) => {
+                let mut string = String::new();
+                for piece in pieces {
+                    string.push_str(&piece.execute(context)?.to_string());
+                }
+                Object::String(string)
+            }
+            AST::Function(name, params, body, _) => {
+                let function = Object::Function(Rc::new(Function {
+                    name: Some(name.clone()),
+                    params: params.clone(),
+                    body: body.clone(),
+                    env: context.clone(),
+                }));
+                context.declare(name, function.clone());
+                function
+            }
+            AST::Lambda(params, body, _) => {
+                Object::Function(Rc::new(Function {
+                    name: None,
+                    params: params.clone(),
+                    body: body.clone(),
+                    env: context.clone(),
+                }))
+            }
+            AST::Call(callee, args, span) => {
+                let mut values = Vec::with_capacity(args.len() + 1);
+                let function = match &**callee {
+                    AST::Field(target, name, field_span) => {
+                        let target = target.execute(context)?;
+                        match target.method(name) {
+                            Some(method) => {
+                                values.push(target);
+                                method
+                            }
+                            None => target.field(name, *field_span).map_err(
+                                |error| match error {
+                                    RuntimeError::UnknownField(
+                                        kind,
+                                        name,
+                                        span,
+                                    ) => RuntimeError::UnknownMethod(
+                                        kind, name, span,
+                                    ),
+                                    error => error,
+                                },
+                            )?,
+                        }
+                    }
+                    _ => callee.execute(context)?,
+                };
+                // The receiver of a method call is passed as `self`, leave it
+                // out of the argument count reported to the user.
+                match (values.first(), function.signature()) {
+                    (Some(_), Some((name, arity)))
+                        if arity != args.len() + 1 =>
+                    {
+                        return Err(RuntimeError::ArityMismatch(
+                            name,
+                            arity.saturating_sub(1),
+                            args.len(),
+                            *span,
+                        )
+                        .into())
+                    }
+                    _ => {}
+                }
+                for arg in args {
+                    values.push(arg.execute(context)?);
+                }
+                function.call(values, *span)?
+            }
+            AST::Index(value, index, span) => {
+                let value = value.execute(context)?;
+                value.index(&index.execute(context)?, *span)?
+            }
+            AST::Slice(value, start, end, span) => {
+                let value = value.execute(context)?;
+                let start = match start {
+                    Some(start) => Some(start.execute(context)?),
+                    None => None,
+                };
+                let end = match end {
+                    Some(end) => Some(end.execute(context)?),
+                    None => None,
+                };
+                value.slice(start, end, *span)?
+            }
+            AST::If(condition, body, otherwise, _) => {
+                if condition.execute(context)?.as_bool(condition.span())? {
+                    run_block(body, &mut context.child())?
+                } else if let Some(otherwise) = otherwise {
+                    run_block(otherwise, &mut context.child())?
+                } else {
+                    Object::Null
+                }
+            }
+            AST::While(condition, body, _) => {
+                while condition.execute(context)?.as_bool(condition.span())? {
+                    if let Some(value) =
+                        run_loop_body(body, &mut context.child())?
+                    {
+                        return Ok(value);
+                    }
+                }
+                Object::Null
+            }
+            AST::For(name, iterable, body, _) => {
+                for item in
+                    iterable.execute(context)?.iterate(iterable.span())?
+                {
+                    let mut scope = context.child();
+                    scope.declare(name, item);
+                    if let Some(value) = run_loop_body(body, &mut scope)? {
+                        return Ok(value);
+                    }
+                }
+                Object::Null
+            }
+            AST::Break(value, span) => {
+                let value = match value {
+                    Some(value) => value.execute(context)?,
+                    None => Object::Null,
+                };
+                return Err(Flow::Break(value, *span));
+            }
+            AST::Continue(span) => return Err(Flow::Continue(*span)),
+            AST::Return(value, span) => {
+                let value = match value {
+                    Some(value) => value.execute(context)?,
+                    None => Object::Null,
+                };
+                return Err(Flow::Return(value, *span));
+            }
+        })
     }
 }
 
-pub fn run(ast: Vec<AST>, context: &mut Context) {
+impl Pattern {
+    // Checks `value` against the pattern, declaring its bindings in `scope`.
+    fn bind(&self, value: &Object, scope: &mut Context) -> Result<bool, Flow> {
+        Ok(match self {
+            Pattern::Wildcard(_) => true,
+            Pattern::Literal(literal) => literal.execute(scope)?.equals(value),
+            Pattern::Binding(name, _) => {
+                scope.declare(name, value.clone());
+                true
+            }
+            Pattern::List(items, rest, _) => {
+                let Object::List(list) = value else {
+                    return Ok(false);
+                };
+                let list = list.borrow().clone();
+                let fits = match rest {
+                    Some(_) => list.len() >= items.len(),
+                    None => list.len() == items.len(),
+                };
+                if !fits {
+                    return Ok(false);
+                }
+                for (item, value) in items.iter().zip(&list) {
+                    if !item.bind(value, scope)? {
+                        return Ok(false);
+                    }
+                }
+                match rest {
+                    Some(rest) => {
+                        let remaining = list[items.len()..].to_vec();
+                        let remaining =
+                            Object::List(Rc::new(RefCell::new(remaining)));
+                        rest.bind(&remaining, scope)?
+                    }
+                    None => true,
+                }
+            }
+            Pattern::Variant(kind, name, fields, span) => {
+                let Some(Object::EnumType(kind)) = scope.get(kind) else {
+                    return Err(match scope.get(kind) {
+                        Some(found) => RuntimeError::TypeMismatch(
+                            "Enum type",
+                            found.type_name(),
+                            *span,
+                        ),
+                        None => {
+                            RuntimeError::UndefinedVariable(kind.clone(), *span)
+                        }
+                    }
+                    .into());
+                };
+                let Some(index) = kind
+                    .variants
+                    .iter()
+                    .position(|(variant, _)| variant == name)
+                else {
+                    return Err(RuntimeError::UnknownVariant(
+                        kind.name.clone(),
+                        name.clone(),
+                        *span,
+                    )
+                    .into());
+                };
+                let expected = kind.variants[index].1.len();
+                if fields.len() != expected {
+                    return Err(RuntimeError::ArityMismatch(
+                        format!("{}.{}", kind.name, name),
+                        expected,
+                        fields.len(),
+                        *span,
+                    )
+                    .into());
+                }
+                let Object::Variant(variant) = value else {
+                    return Ok(false);
+                };
+                if !Rc::ptr_eq(&variant.kind, &kind) || variant.index != index {
+                    return Ok(false);
+                }
+                for (field, value) in fields.iter().zip(&variant.values) {
+                    if !field.bind(value, scope)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
+        })
+    }
+}
+
+fn run_block(body: &[AST], context: &mut Context) -> Result<Object, Flow> {
+    let mut result = Object::Null;
+    for expression in body {
+        result = expression.execute(context)?;
+    }
+    Ok(result)
+}
+
+fn run_loop_body(
+    body: &[AST],
+    context: &mut Context,
+) -> Result<Option<Object>, Flow> {
+    match run_block(body, context) {
+        Ok(_) | Err(Flow::Continue(_)) => Ok(None),
+        Err(Flow::Break(value, _)) => Ok(Some(value)),
+        Err(flow) => Err(flow),
+    }
+}
+
+pub fn run(ast: Vec<AST>, context: &mut Context) -> Result<(), RuntimeError> {
     for (l, a) in ast.iter().enumerate() {
-        // println!("Something");
-        //let mut cntx = context.clone();
-        println!("{}: {}", l, a.execute(context));
-        dbg!(&context);
+        let value = a.execute(context).map_err(Flow::into_error)?;
+        match a {
+            AST::Let(name, _, _) => println!("{}: {} = {}", l, name, value),
+            AST::BinaryOp(op, lhs, _, _) if op == "=" => match &**lhs {
+                AST::Variable(name, _) => {
+                    println!("{}: {} = {}", l, name, value)
+                }
+                _ => println!("{}: {}", l, value),
+            },
+            _ => println!("{}: {}", l, value),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::tokenize;
+
+    fn eval(source: &str) -> Result<Object, RuntimeError> {
+        let mut tokens = tokenize(source).unwrap();
+        tokens.reverse();
+        let ast = crate::parser::parse(&mut tokens).unwrap();
+        let mut context = Context::new();
+        builtins::register(&context);
+        run_block(&ast, &mut context).map_err(Flow::into_error)
+    }
+
+    fn show(source: &str) -> String {
+        eval(source).unwrap().to_string()
+    }
+
+    #[test]
+    fn division_is_exact() {
+        assert!(matches!(eval("6 / 3"), Ok(Object::Int(2))));
+        assert!(matches!(eval("7 / 2"), Ok(Object::Rational(_))));
+        assert_eq!(show("7 / 2"), "7/2");
+        assert_eq!(show("1 / 3 + 1 / 6"), "1/2");
+        assert_eq!(show("1 / 2 * 4"), "2");
+        assert_eq!(show("7 % 2"), "1");
+        assert_eq!(show("1.0 / 4"), "0.25");
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        assert!(matches!(
+            eval("1 / 0"),
+            Err(RuntimeError::DivisionByZero(_))
+        ));
+        assert!(matches!(
+            eval("1 % 0"),
+            Err(RuntimeError::DivisionByZero(_))
+        ));
+    }
+
+    #[test]
+    fn huge_powers_overflow() {
+        assert_eq!(show("2 ** 64"), "18446744073709551616");
+        assert_eq!(show("(1 / 2) ** 3"), "1/8");
+        assert_eq!(show("1 ** 4000000000"), "1");
+        assert!(matches!(
+            eval("2 ** 4000000000"),
+            Err(RuntimeError::Overflow(_, _))
+        ));
+        assert!(matches!(
+            eval("(2 / 3) ** 4000000000"),
+            Err(RuntimeError::Overflow(_, _))
+        ));
+    }
+
+    #[test]
+    fn overflow_promotes_to_big_integers() {
+        let max = "9223372036854775807";
+        assert!(matches!(eval(max), Ok(Object::Int(i64::MAX))));
+        assert!(matches!(
+            eval(&format!("{} + 1", max)),
+            Ok(Object::BigInt(_))
+        ));
+        assert_eq!(show(&format!("{} + 1", max)), "9223372036854775808");
+        assert_eq!(show(&format!("-{} - 2", max)), "-9223372036854775809");
+        assert_eq!(show(&format!("{} * 10", max)), "92233720368547758070");
+        assert_eq!(show(&format!("-(0 - {} - 1)", max)), "9223372036854775808");
+    }
+
+    #[test]
+    fn big_results_that_fit_become_ints_again() {
+        let max = "9223372036854775807";
+        assert!(matches!(
+            eval(&format!("({} + 1) - 1", max)),
+            Ok(Object::Int(i64::MAX))
+        ));
+        assert!(matches!(eval("2 ** 70 / 2 ** 69"), Ok(Object::Int(2))));
+    }
+
+    #[test]
+    fn lists_are_shared_between_bindings() {
+        assert_eq!(
+            show("let a = [1]; let b = a; push(b, 2); b[0] = 9; a"),
+            "[9, 2]"
+        );
+        assert_eq!(
+            show("let a = [1]; fun f(xs) { push(xs, 2) }; f(a); a"),
+            "[1, 2]"
+        );
+        assert_eq!(
+            show("let a = [1]; let d = [a, a]; d[0][0] = 7; d[1]"),
+            "[7]"
+        );
+    }
+
+    #[test]
+    fn slices_are_copies() {
+        assert_eq!(
+            show("let a = [1, 2, 3]; let c = a[1:3]; c[0] = 0; a"),
+            "[1, 2, 3]"
+        );
+    }
+
+    #[test]
+    fn cyclic_lists_display_and_compare() {
+        assert_eq!(show("let xs = [1]; push(xs, xs); xs"), "[1, [...]]");
+        assert!(matches!(
+            eval("let xs = [1]; push(xs, xs); xs == xs"),
+            Ok(Object::Bool(true))
+        ));
+        assert_eq!(
+            show("struct P { x }; let p = P { x: 1 }; p.x = p; p"),
+            "P { x: P {...} }"
+        );
+    }
+
+    #[test]
+    fn match_guards_fall_through() {
+        let shape = "enum Shape { Circle(r), Rect(w, h) }; \
+            fun area(s) { match s { \
+                Shape.Circle(r) -> r, \
+                Shape.Rect(w, h) if w == h -> \"square\", \
+                Shape.Rect(w, h) -> w * h, \
+            } }; ";
+        assert_eq!(show(&format!("{}area(Shape.Rect(2, 3))", shape)), "6");
+        assert_eq!(show(&format!("{}area(Shape.Rect(2, 2))", shape)), "square");
+        assert_eq!(show("match 5 { n if n > 3 -> 1, _ -> 2 }"), "1");
+        assert_eq!(show("match 2 { n if n > 3 -> 1, _ -> 2 }"), "2");
+    }
+
+    #[test]
+    fn rest_patterns_bind_the_remaining_items() {
+        assert_eq!(show("match [1, 2, 3] { [x, ..rest] -> rest }"), "[2, 3]");
+        assert_eq!(show("match [1] { [x, ..rest] -> rest }"), "[]");
+        assert_eq!(show("match [1, 2, 3] { [x, ..] -> x }"), "1");
+        assert_eq!(show("match [1] { [x, y, ..] -> 1, _ -> 2 }"), "2");
+        assert_eq!(show("match [1, [2, 3]] { [a, [b, c]] -> a + b + c }"), "6");
+    }
+
+    #[test]
+    fn unmatched_values_are_an_error() {
+        assert!(matches!(
+            eval("match 9 { 1 -> 1 }"),
+            Err(RuntimeError::NoMatch(_, _))
+        ));
     }
 }
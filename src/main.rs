@@ -1,43 +1,64 @@
+pub mod builtins;
+pub mod input;
 pub mod interpret;
 pub mod parser;
 pub mod tokenizer;
 
 use std::{
-    collections::HashMap,
     env, fs,
     io::{self, Write},
+    process,
 };
 
+use input::{Diagnostic, SourceMap};
+
+fn execute(
+    source: &str,
+    context: &mut interpret::Context,
+) -> Result<(), Diagnostic> {
+    let mut tokens = tokenizer::tokenize(source)?;
+    tokens.reverse();
+    let ast = parser::parse(&mut tokens)?;
+    interpret::run(ast, context)?;
+    Ok(())
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let mut context = interpret::Context {
-        variables: HashMap::from([(
-            String::from("pi"),
-            interpret::Object::Float(std::f64::consts::PI),
-        )]),
-    };
+    let mut context = interpret::Context::new();
+    builtins::register(&context);
     // dbg!(&args);
     if args.len() > 1 {
-        let source = fs::read_to_string(&args[1]).expect("Coudn't open file.");
-        let mut tokens = tokenizer::tokenize(&source);
-        // dbg!(&tokens);
-        let ast = parser::parse(&mut tokens);
-        interpret::run(ast, &mut context);
+        let source = match fs::read_to_string(&args[1]) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("error: Couldn't read `{}`: {}", args[1], error);
+                process::exit(1);
+            }
+        };
+        if let Err(error) = execute(&source, &mut context) {
+            eprintln!("{}", error.render(&SourceMap::new(&args[1], &source)));
+            process::exit(1);
+        }
     } else {
         loop {
             let mut input: String = String::new();
             print!(">>> ");
             let _ = io::stdout().flush();
-            let _ = io::stdin().read_line(&mut input);
-            let mut tokens = tokenizer::tokenize(&input);
-            dbg!(&tokens);
-            // dbg!(&context);
-            tokens.reverse();
-            let ast = parser::parse(&mut tokens);
-            // dbg!(&context);
-            dbg!(&ast);
-            interpret::run(ast, &mut context);
-            // dbg!(&context);
+            match io::stdin().read_line(&mut input) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(error) => {
+                    eprintln!("Error: {}", error);
+                    break;
+                }
+            }
+            if let Err(error) = execute(&input, &mut context) {
+                eprintln!(
+                    "{}",
+                    error.render(&SourceMap::new("<stdin>", &input))
+                );
+            }
         }
     }
 }
@@ -1,234 +1,1096 @@
-use core::panic;
-
 extern crate lazy_static;
 
 use lazy_static::lazy_static;
 
-use crate::tokenizer::Token;
-use std::collections::HashMap;
+use crate::input::{Diagnostic, Span};
+use crate::tokenizer::{TemplatePart, Token};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::{collections::HashMap, fmt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Fixity {
+    Prefix,
+    Infix,
+    Postfix,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Assoc {
+    Left,
+    Right,
+}
 
 lazy_static! {
-    static ref BINOP_PRECEDENSE: HashMap<&'static str, i32> = HashMap::from([
-        (" ", -10),
-        ("+", 10),
-        ("-", 10),
-        ("*", 20),
-        ("%", 20),
-        ("/", 20),
-        ("**", 30),
-        ("=", 1)
-    ]);
+    // Binding powers for the Pratt parser in `parse_expression_bp`, higher
+    // binds tighter. Prefix operators sit below `**`, so `-2**2` is
+    // `-(2**2)`, and postfix calls, indexing and field access bind tightest.
+    static ref OPERATORS: HashMap<(Fixity, &'static str), (u8, Assoc)> =
+        HashMap::from([
+            ((Fixity::Infix, "="), (1, Assoc::Right)),
+            ((Fixity::Infix, "||"), (3, Assoc::Left)),
+            ((Fixity::Infix, "&&"), (4, Assoc::Left)),
+            ((Fixity::Infix, "=="), (7, Assoc::Left)),
+            ((Fixity::Infix, "!="), (7, Assoc::Left)),
+            ((Fixity::Infix, "<"), (8, Assoc::Left)),
+            ((Fixity::Infix, "<="), (8, Assoc::Left)),
+            ((Fixity::Infix, ">"), (8, Assoc::Left)),
+            ((Fixity::Infix, ">="), (8, Assoc::Left)),
+            ((Fixity::Infix, ".."), (9, Assoc::Left)),
+            ((Fixity::Infix, "+"), (10, Assoc::Left)),
+            ((Fixity::Infix, "-"), (10, Assoc::Left)),
+            ((Fixity::Infix, "*"), (20, Assoc::Left)),
+            ((Fixity::Infix, "/"), (20, Assoc::Left)),
+            ((Fixity::Infix, "%"), (20, Assoc::Left)),
+            ((Fixity::Prefix, "+"), (25, Assoc::Right)),
+            ((Fixity::Prefix, "-"), (25, Assoc::Right)),
+            ((Fixity::Prefix, "!"), (25, Assoc::Right)),
+            ((Fixity::Infix, "**"), (30, Assoc::Right)),
+            ((Fixity::Postfix, "("), (40, Assoc::Left)),
+            ((Fixity::Postfix, "["), (40, Assoc::Left)),
+            ((Fixity::Postfix, "."), (40, Assoc::Left)),
+        ]);
 }
 
 #[derive(Debug, Clone)]
 pub enum AST {
-    Number(f64),
-    Variable(String),
-    UnaryOp(String, Box<AST>),
-    BinaryOp(String, Box<AST>, Box<AST>),
-    Call(String, Vec<AST>),
-    Function(String, Vec<AST>, Vec<AST>),
-    String(String),
-}
-
-fn parse_number(tokens: &mut Vec<Token>) -> AST {
-    AST::Number(if let Token::Numb(n, _) = tokens.pop().unwrap() {
-        n
-    } else {
-        panic!("WTF")
-    })
+    Int(i64, Span),
+    BigInt(BigInt, Span),
+    Number(f64, Span),
+    Bool(bool, Span),
+    Variable(String, Span),
+    Let(String, Box<AST>, Span),
+    UnaryOp(String, Box<AST>, Span),
+    BinaryOp(String, Box<AST>, Box<AST>, Span),
+    Call(Box<AST>, Vec<AST>, Span),
+    Index(Box<AST>, Box<AST>, Span),
+    Slice(Box<AST>, Option<Box<AST>>, Option<Box<AST>>, Span),
+    Function(String, Vec<AST>, Vec<AST>, Span),
+    Lambda(Vec<AST>, Vec<AST>, Span),
+    If(Box<AST>, Vec<AST>, Option<Vec<AST>>, Span),
+    While(Box<AST>, Vec<AST>, Span),
+    For(String, Box<AST>, Vec<AST>, Span),
+    Break(Option<Box<AST>>, Span),
+    Continue(Span),
+    Return(Option<Box<AST>>, Span),
+    String(String, Span),
+    List(Vec<AST>, Span),
+    Map(Vec<(AST, AST)>, Span),
+    Struct(String, Vec<String>, Span),
+    StructLiteral(String, Vec<(String, AST)>, Span),
+    Field(Box<AST>, String, Span),
+    Enum(String, Vec<(String, Vec<String>)>, Span),
+    Impl(String, Vec<AST>, Span),
+    Match(Box<AST>, Vec<(Pattern, Option<AST>, Vec<AST>)>, Span),
+    Interpolation(Vec<AST>, Span),
+}
+
+impl AST {
+    pub fn span(&self) -> Span {
+        match self {
+            AST::Int(_, span)
+            | AST::BigInt(_, span)
+            | AST::Number(_, span)
+            | AST::Bool(_, span)
+            | AST::Variable(_, span)
+            | AST::Let(_, _, span)
+            | AST::UnaryOp(_, _, span)
+            | AST::BinaryOp(_, _, _, span)
+            | AST::Call(_, _, span)
+            | AST::Index(_, _, span)
+            | AST::Slice(_, _, _, span)
+            | AST::Function(_, _, _, span)
+            | AST::Lambda(_, _, span)
+            | AST::If(_, _, _, span)
+            | AST::While(_, _, span)
+            | AST::For(_, _, _, span)
+            | AST::Break(_, span)
+            | AST::Continue(span)
+            | AST::Return(_, span)
+            | AST::String(_, span)
+            | AST::List(_, span)
+            | AST::Map(_, span)
+            | AST::Struct(_, _, span)
+            | AST::StructLiteral(_, _, span)
+            | AST::Field(_, _, span)
+            | AST::Enum(_, _, span)
+            | AST::Impl(_, _, span)
+            | AST::Match(_, _, span)
+            | AST::Interpolation(_, span) => *span,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Wildcard(Span),
+    Literal(AST),
+    Binding(String, Span),
+    // Items followed by an optional `..rest` pattern for the remaining items.
+    List(Vec<Pattern>, Option<Box<Pattern>>, Span),
+    Variant(String, String, Vec<Pattern>, Span),
 }
 
-fn parse_bin_op_rhs(
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Literal(literal) => literal.span(),
+            Pattern::Wildcard(span)
+            | Pattern::Binding(_, span)
+            | Pattern::List(_, _, span)
+            | Pattern::Variant(_, _, _, span) => *span,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    Expected(&'static str, Token),
+    UnexpectedToken(Token),
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::Expected(_, token)
+            | ParseError::UnexpectedToken(token) => token.span(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Expected(expected, found) => {
+                write!(f, "Expected {}, found {}.", expected, found)
+            }
+            ParseError::UnexpectedToken(token) => {
+                write!(f, "Unexpected {}.", token)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for Diagnostic {
+    fn from(error: ParseError) -> Diagnostic {
+        Diagnostic::new(error.to_string(), error.span())
+    }
+}
+
+// The token stream is reversed and always ends with `Token::EOF`, which is
+// never popped, so every parser can look at the next token unconditionally.
+fn peek(tokens: &[Token]) -> &Token {
+    tokens.last().expect("Token stream must end with EOF.")
+}
+
+fn next(tokens: &mut Vec<Token>) -> Token {
+    if tokens.len() == 1 {
+        return tokens[0].clone();
+    }
+    tokens.pop().expect("Token stream must end with EOF.")
+}
+
+fn parse_number(tokens: &mut Vec<Token>) -> Result<AST, ParseError> {
+    match next(tokens) {
+        Token::Int(n, span) => Ok(match n.to_i64() {
+            Some(n) => AST::Int(n, span),
+            None => AST::BigInt(n, span),
+        }),
+        Token::Numb(n, span) => Ok(AST::Number(n, span)),
+        token => Err(ParseError::Expected("number", token)),
+    }
+}
+
+fn parse_paren(tokens: &mut Vec<Token>) -> Result<AST, ParseError> {
+    next(tokens);
+    let lhs = parse_expression(tokens)?;
+    match next(tokens) {
+        Token::RParen(_) => Ok(lhs),
+        token => Err(ParseError::Expected("`)`", token)),
+    }
+}
+
+fn parse_init(tokens: &mut Vec<Token>, start: Span) -> Result<AST, ParseError> {
+    let name = match next(tokens) {
+        Token::Ident(id, _) => id,
+        token => return Err(ParseError::Expected("identifier", token)),
+    };
+    match next(tokens) {
+        Token::Operator(op, _) if op == "=" => {}
+        token => return Err(ParseError::Expected("`=`", token)),
+    }
+    let value = parse_expression(tokens)?;
+    let span = start.to(value.span());
+    Ok(AST::Let(name, Box::new(value), span))
+}
+
+fn parse_block(
+    tokens: &mut Vec<Token>,
+) -> Result<(Vec<AST>, Span), ParseError> {
+    let start = match next(tokens) {
+        Token::StartBlock(span) => span,
+        token => return Err(ParseError::Expected("code block", token)),
+    };
+    let body = parse_statements(tokens)?;
+    match next(tokens) {
+        Token::EndBlock(end) => Ok((body, start.to(end))),
+        token => Err(ParseError::Expected("`}`", token)),
+    }
+}
+
+fn parse_function_def(
     tokens: &mut Vec<Token>,
-    lhs_: AST,
-    min_token_precedense: i32,
-) -> AST {
-    let mut lhs = lhs_.clone();
+    start: Span,
+) -> Result<AST, ParseError> {
+    let mut params: Vec<AST> = vec![];
+    let name = match peek(tokens) {
+        Token::LParen(_) => None,
+        _ => match next(tokens) {
+            Token::Ident(name, _) => Some(name),
+            token => return Err(ParseError::Expected("function name", token)),
+        },
+    };
+    match next(tokens) {
+        Token::LParen(_) => {}
+        token => return Err(ParseError::Expected("`(`", token)),
+    }
     loop {
-        let tok_precedense;
-        {
-            let cur_tok = tokens.last().unwrap();
-            tok_precedense = BINOP_PRECEDENSE
-                .get(if let Token::Operator(op, _) = cur_tok {
-                    op.as_str()
-                } else {
-                    return lhs;
-                })
-                .unwrap_or(&-1);
-        }
-        if *tok_precedense < min_token_precedense {
-            return lhs;
-        }
-        let operator = tokens.pop().unwrap();
-        let mut rhs = parse_primary(tokens);
-        let next_tok_precedense = BINOP_PRECEDENSE
-            .get(if let Token::Operator(op, _) = tokens.last().unwrap() {
-                op.as_str()
-            } else {
-                " "
-            })
-            .unwrap_or(&-1);
-        if tok_precedense < next_tok_precedense {
-            rhs = parse_bin_op_rhs(tokens, rhs, tok_precedense + 1);
-        }
-        lhs = AST::BinaryOp(
-            if let Token::Operator(op, _) = operator {
-                op
-            } else {
-                panic!("Imposible")
-            },
-            Box::new(lhs),
-            Box::new(rhs),
-        );
+        match next(tokens) {
+            Token::Ident(name, span) => params.push(AST::Variable(name, span)),
+            Token::RParen(_) => break,
+            token => return Err(ParseError::Expected("parameter name", token)),
+        }
+        match peek(tokens) {
+            Token::Comma(_) => {
+                next(tokens);
+            }
+            Token::RParen(_) => {}
+            _ => return Err(ParseError::Expected("`,` or `)`", next(tokens))),
+        }
     }
+
+    let (body, end) = parse_block(tokens)?;
+    Ok(match name {
+        Some(name) => AST::Function(name, params, body, start.to(end)),
+        None => AST::Lambda(params, body, start.to(end)),
+    })
 }
 
-fn parse_paren(tokens: &mut Vec<Token>) -> AST {
-    tokens.pop();
-    let lhs = parse_expression(tokens);
-    if !matches!(tokens.pop().unwrap(), Token::RParen(_)) {
-        panic!("Expected ')'");
+fn parse_if(tokens: &mut Vec<Token>, start: Span) -> Result<AST, ParseError> {
+    let condition = parse_condition(tokens)?;
+    let (body, mut end) = parse_block(tokens)?;
+    let mut otherwise = None;
+    if matches!(peek(tokens), Token::Ident(id, _) if id == "else") {
+        next(tokens);
+        let is_else_if =
+            matches!(peek(tokens), Token::Ident(id, _) if id == "if");
+        let (branch, branch_end) = if is_else_if {
+            let span = next(tokens).span();
+            let nested = parse_if(tokens, span)?;
+            let span = nested.span();
+            (vec![nested], span)
+        } else {
+            parse_block(tokens)?
+        };
+        otherwise = Some(branch);
+        end = branch_end;
     }
+    Ok(AST::If(Box::new(condition), body, otherwise, start.to(end)))
+}
 
-    lhs
+fn parse_while(
+    tokens: &mut Vec<Token>,
+    start: Span,
+) -> Result<AST, ParseError> {
+    let condition = parse_condition(tokens)?;
+    let (body, end) = parse_block(tokens)?;
+    Ok(AST::While(Box::new(condition), body, start.to(end)))
 }
 
-fn parse_unary(tokens: &mut Vec<Token>) -> AST {
-    let op = if let Token::Operator(op, _) = tokens.pop().unwrap() {
-        op
-    } else {
-        panic!("Imposible")
+fn parse_for(tokens: &mut Vec<Token>, start: Span) -> Result<AST, ParseError> {
+    let name = match next(tokens) {
+        Token::Ident(name, _) => name,
+        token => return Err(ParseError::Expected("loop variable", token)),
     };
-    AST::UnaryOp(op, Box::new(parse_primary(tokens)))
+    match next(tokens) {
+        Token::Ident(id, _) if id == "in" => {}
+        token => return Err(ParseError::Expected("`in`", token)),
+    }
+    let iterable = parse_condition(tokens)?;
+    let (body, end) = parse_block(tokens)?;
+    Ok(AST::For(name, Box::new(iterable), body, start.to(end)))
+}
+
+fn parse_jump_value(
+    tokens: &mut Vec<Token>,
+    start: Span,
+) -> Result<(Option<Box<AST>>, Span), ParseError> {
+    // Anything that can close the surrounding construct means no value.
+    if matches!(
+        peek(tokens),
+        Token::Delim(_)
+            | Token::Comma(_)
+            | Token::RParen(_)
+            | Token::RBracket(_)
+            | Token::EndBlock(_)
+            | Token::EOF(_)
+    ) {
+        return Ok((None, start));
+    }
+    let value = parse_expression(tokens)?;
+    let span = start.to(value.span());
+    Ok((Some(Box::new(value)), span))
+}
+
+fn parse_call(tokens: &mut Vec<Token>) -> Result<(Vec<AST>, Span), ParseError> {
+    let mut args = vec![];
+    next(tokens);
+    loop {
+        if let Token::RParen(end) = peek(tokens) {
+            let end = *end;
+            next(tokens);
+            return Ok((args, end));
+        }
+        args.push(parse_expression(tokens)?);
+        match peek(tokens) {
+            Token::Comma(_) => {
+                next(tokens);
+            }
+            Token::RParen(_) => {}
+            _ => return Err(ParseError::Expected("`,` or `)`", next(tokens))),
+        }
+    }
 }
 
-fn parse_init(tokens: &mut Vec<Token>) -> AST {
-    let lhs = match tokens.pop().unwrap() {
-        Token::Ident(id, _) => AST::Variable(id),
-        _ => panic!("Expected identifier."),
+fn parse_struct_def(
+    tokens: &mut Vec<Token>,
+    start: Span,
+) -> Result<AST, ParseError> {
+    let name = match next(tokens) {
+        Token::Ident(name, _) => name,
+        token => return Err(ParseError::Expected("struct name", token)),
     };
+    match next(tokens) {
+        Token::StartBlock(_) => {}
+        token => return Err(ParseError::Expected("`{`", token)),
+    }
+    let mut fields = vec![];
+    loop {
+        match next(tokens) {
+            Token::EndBlock(end) => {
+                return Ok(AST::Struct(name, fields, start.to(end)))
+            }
+            Token::Ident(field, _) => fields.push(field),
+            token => return Err(ParseError::Expected("field name", token)),
+        }
+        match peek(tokens) {
+            Token::Comma(_) => {
+                next(tokens);
+            }
+            Token::EndBlock(_) => {}
+            _ => return Err(ParseError::Expected("`,` or `}`", next(tokens))),
+        }
+    }
+}
 
-    parse_bin_op_rhs(tokens, lhs, 0)
+fn parse_enum_def(
+    tokens: &mut Vec<Token>,
+    start: Span,
+) -> Result<AST, ParseError> {
+    let name = match next(tokens) {
+        Token::Ident(name, _) => name,
+        token => return Err(ParseError::Expected("enum name", token)),
+    };
+    match next(tokens) {
+        Token::StartBlock(_) => {}
+        token => return Err(ParseError::Expected("`{`", token)),
+    }
+    let mut variants = vec![];
+    loop {
+        let variant = match next(tokens) {
+            Token::EndBlock(end) => {
+                return Ok(AST::Enum(name, variants, start.to(end)))
+            }
+            Token::Ident(variant, _) => variant,
+            token => return Err(ParseError::Expected("variant name", token)),
+        };
+        let mut fields = vec![];
+        if let Token::LParen(_) = peek(tokens) {
+            next(tokens);
+            loop {
+                match next(tokens) {
+                    Token::RParen(_) => break,
+                    Token::Ident(field, _) => fields.push(field),
+                    token => {
+                        return Err(ParseError::Expected("field name", token))
+                    }
+                }
+                match peek(tokens) {
+                    Token::Comma(_) => {
+                        next(tokens);
+                    }
+                    Token::RParen(_) => {}
+                    _ => {
+                        return Err(ParseError::Expected(
+                            "`,` or `)`",
+                            next(tokens),
+                        ))
+                    }
+                }
+            }
+        }
+        variants.push((variant, fields));
+        match peek(tokens) {
+            Token::Comma(_) => {
+                next(tokens);
+            }
+            Token::EndBlock(_) => {}
+            _ => return Err(ParseError::Expected("`,` or `}`", next(tokens))),
+        }
+    }
 }
 
-fn parse_function_def(tokens: &mut Vec<Token>) -> AST {
-    let mut params: Vec<AST> = vec![];
-    let Token::Ident(name, _) = tokens.pop().unwrap() else {
-        panic!("Expected function name")
+fn parse_impl(tokens: &mut Vec<Token>, start: Span) -> Result<AST, ParseError> {
+    let name = match next(tokens) {
+        Token::Ident(name, _) => name,
+        token => return Err(ParseError::Expected("type name", token)),
     };
-    if let Token::LParen(_) = tokens.pop().unwrap() {
-    } else {
-        panic!("Expected `(`")
+    match next(tokens) {
+        Token::StartBlock(_) => {}
+        token => return Err(ParseError::Expected("`{`", token)),
     }
-    while let Token::Ident(name, _) = tokens.pop().unwrap() {
-        params.push(AST::Variable(name))
+    let mut methods = vec![];
+    loop {
+        match peek(tokens) {
+            Token::EndBlock(end) => {
+                let span = start.to(*end);
+                next(tokens);
+                return Ok(AST::Impl(name, methods, span));
+            }
+            Token::Delim(_) => {
+                next(tokens);
+            }
+            Token::Ident(id, _)
+                if id == "fun"
+                    && matches!(
+                        tokens.iter().nth_back(1),
+                        Some(Token::Ident(_, _))
+                    ) =>
+            {
+                methods.push(parse_ident(tokens, true)?);
+            }
+            _ => {
+                return Err(ParseError::Expected(
+                    "method definition",
+                    next(tokens),
+                ))
+            }
+        }
     }
+}
 
-    if let Token::StartBlock(_) = tokens.pop().unwrap() {
-    } else {
-        panic!("Expected code block")
+// Parses comma separated patterns up to and including the closing token into
+// a `Pattern::List`, a `..rest` pattern is only accepted inside lists.
+fn parse_patterns(
+    tokens: &mut Vec<Token>,
+    start: Span,
+    close: fn(&Token) -> bool,
+    expected: &'static str,
+    allow_rest: bool,
+) -> Result<Pattern, ParseError> {
+    let mut patterns = vec![];
+    let mut rest = None;
+    loop {
+        if close(peek(tokens)) {
+            let span = start.to(next(tokens).span());
+            return Ok(Pattern::List(patterns, rest, span));
+        }
+        match peek(tokens) {
+            Token::Operator(op, _)
+                if op == ".." && allow_rest && rest.is_none() =>
+            {
+                let span = next(tokens).span();
+                rest = Some(Box::new(match peek(tokens) {
+                    Token::Ident(_, _) => parse_pattern(tokens)?,
+                    _ => Pattern::Wildcard(span),
+                }));
+            }
+            _ if rest.is_some() => {
+                return Err(ParseError::Expected(expected, next(tokens)))
+            }
+            _ => patterns.push(parse_pattern(tokens)?),
+        }
+        match peek(tokens) {
+            Token::Comma(_) => {
+                next(tokens);
+            }
+            token if close(token) => {}
+            _ => return Err(ParseError::Expected(expected, next(tokens))),
+        }
     }
-    dbg!(&tokens);
-    let next = parse(tokens);
-    dbg!(&next);
-    AST::Function(name, params, next)
 }
 
-fn parse_call(tokens: &mut Vec<Token>) -> Vec<AST> {
-    let mut params = vec![];
-    tokens.pop();
-    while matches!(tokens.last().unwrap(), Token::RParen(_)) {
-        params.push(parse_primary(tokens));
+fn parse_pattern(tokens: &mut Vec<Token>) -> Result<Pattern, ParseError> {
+    match peek(tokens) {
+        Token::Int(_, _) | Token::Numb(_, _) => {
+            Ok(Pattern::Literal(parse_number(tokens)?))
+        }
+        Token::String(_, _) => Ok(Pattern::Literal(parse_string(tokens)?)),
+        Token::Operator(op, _) if op == "-" => {
+            let start = next(tokens).span();
+            let number = parse_number(tokens)?;
+            let span = start.to(number.span());
+            Ok(Pattern::Literal(AST::UnaryOp(
+                "-".to_string(),
+                Box::new(number),
+                span,
+            )))
+        }
+        Token::LBracket(_) => {
+            let start = next(tokens).span();
+            parse_patterns(
+                tokens,
+                start,
+                |token| matches!(token, Token::RBracket(_)),
+                "`,` or `]`",
+                true,
+            )
+        }
+        Token::Ident(_, _) => {
+            let Token::Ident(name, span) = next(tokens) else {
+                unreachable!("Identifier was peeked above.")
+            };
+            match name.as_str() {
+                "_" => return Ok(Pattern::Wildcard(span)),
+                "true" => return Ok(Pattern::Literal(AST::Bool(true, span))),
+                "false" => return Ok(Pattern::Literal(AST::Bool(false, span))),
+                _ => {}
+            }
+            if !matches!(peek(tokens), Token::Operator(op, _) if op == ".") {
+                return Ok(Pattern::Binding(name, span));
+            }
+            next(tokens);
+            let (variant, mut end) = match next(tokens) {
+                Token::Ident(variant, end) => (variant, end),
+                token => {
+                    return Err(ParseError::Expected("variant name", token))
+                }
+            };
+            let mut fields = vec![];
+            if let Token::LParen(_) = peek(tokens) {
+                let start = next(tokens).span();
+                let Pattern::List(items, _, list) = parse_patterns(
+                    tokens,
+                    start,
+                    |token| matches!(token, Token::RParen(_)),
+                    "`,` or `)`",
+                    false,
+                )?
+                else {
+                    unreachable!("Patterns are parsed into a list.")
+                };
+                (fields, end) = (items, list);
+            }
+            Ok(Pattern::Variant(name, variant, fields, span.to(end)))
+        }
+        _ => Err(ParseError::Expected("pattern", next(tokens))),
+    }
+}
+
+fn parse_match(
+    tokens: &mut Vec<Token>,
+    start: Span,
+) -> Result<AST, ParseError> {
+    let subject = parse_condition(tokens)?;
+    match next(tokens) {
+        Token::StartBlock(_) => {}
+        token => return Err(ParseError::Expected("`{`", token)),
+    }
+    let mut arms = vec![];
+    loop {
+        if let Token::EndBlock(end) = peek(tokens) {
+            let span = start.to(*end);
+            next(tokens);
+            return Ok(AST::Match(Box::new(subject), arms, span));
+        }
+        let pattern = parse_pattern(tokens)?;
+        let guard = match peek(tokens) {
+            Token::Ident(id, _) if id == "if" => {
+                next(tokens);
+                Some(parse_expression(tokens)?)
+            }
+            _ => None,
+        };
+        match next(tokens) {
+            Token::Operator(op, _) if op == "->" => {}
+            token => return Err(ParseError::Expected("`->`", token)),
+        }
+        // An arm starting with `{` is a block, wrap maps in parentheses.
+        let (body, block) = match peek(tokens) {
+            Token::StartBlock(_) => (parse_block(tokens)?.0, true),
+            _ => (vec![parse_expression(tokens)?], false),
+        };
+        arms.push((pattern, guard, body));
+        // Arms are separated by `,` or `;`, which is optional after a block.
+        match peek(tokens) {
+            Token::Comma(_) | Token::Delim(_) => {
+                next(tokens);
+            }
+            Token::EndBlock(_) => {}
+            _ if block => {}
+            _ => return Err(ParseError::Expected("`,` or `}`", next(tokens))),
+        }
+    }
+}
+
+// `name {` is a struct literal when the braces are empty or start with
+// `field:`. Conditions are parsed by `parse_condition`, which never asks, so
+// `if ready { ... }` is still a condition followed by a block.
+fn is_struct_literal(tokens: &[Token]) -> bool {
+    let mut ahead = tokens.iter().rev();
+    matches!(ahead.next(), Some(Token::StartBlock(_)))
+        && match ahead.next() {
+            Some(Token::EndBlock(_)) => true,
+            Some(Token::Ident(_, _)) => {
+                matches!(ahead.next(), Some(Token::Colon(_)))
+            }
+            _ => false,
+        }
+}
+
+fn parse_struct_literal(
+    tokens: &mut Vec<Token>,
+    name: String,
+    start: Span,
+) -> Result<AST, ParseError> {
+    next(tokens);
+    let mut fields = vec![];
+    loop {
+        let field = match next(tokens) {
+            Token::EndBlock(end) => {
+                return Ok(AST::StructLiteral(name, fields, start.to(end)))
+            }
+            Token::Ident(field, _) => field,
+            token => return Err(ParseError::Expected("field name", token)),
+        };
+        match next(tokens) {
+            Token::Colon(_) => {}
+            token => return Err(ParseError::Expected("`:`", token)),
+        }
+        fields.push((field, parse_expression(tokens)?));
+        match peek(tokens) {
+            Token::Comma(_) => {
+                next(tokens);
+            }
+            Token::EndBlock(_) => {}
+            _ => return Err(ParseError::Expected("`,` or `}`", next(tokens))),
+        }
     }
-    tokens.pop();
-    params
 }
 
-fn parse_ident(tokens: &mut Vec<Token>) -> AST {
-    match tokens.pop().unwrap() {
-        Token::Ident(id, _) => {
-            match id.as_str() {
-                "let" => parse_init(tokens),
-                "fun" => parse_function_def(tokens),
-                _ => {
-                    if let Token::LParen(_) = tokens.last().unwrap() {
-                        return AST::Call(id, parse_call(tokens));
+fn parse_ident(
+    tokens: &mut Vec<Token>,
+    struct_literals: bool,
+) -> Result<AST, ParseError> {
+    match next(tokens) {
+        Token::Ident(id, span) => match id.as_str() {
+            "let" => parse_init(tokens, span),
+            "fun" => parse_function_def(tokens, span),
+            "if" => parse_if(tokens, span),
+            "while" => parse_while(tokens, span),
+            "for" => parse_for(tokens, span),
+            "struct" => parse_struct_def(tokens, span),
+            "enum" => parse_enum_def(tokens, span),
+            "match" => parse_match(tokens, span),
+            "impl" => parse_impl(tokens, span),
+            "break" => {
+                let (value, span) = parse_jump_value(tokens, span)?;
+                Ok(AST::Break(value, span))
+            }
+            "continue" => Ok(AST::Continue(span)),
+            "return" => {
+                let (value, span) = parse_jump_value(tokens, span)?;
+                Ok(AST::Return(value, span))
+            }
+            "true" => Ok(AST::Bool(true, span)),
+            "false" => Ok(AST::Bool(false, span)),
+            _ if struct_literals && is_struct_literal(tokens) => {
+                parse_struct_literal(tokens, id, span)
+            }
+            _ => Ok(AST::Variable(id, span)),
+        },
+        token => Err(ParseError::Expected("identifier", token)),
+    }
+}
+
+fn parse_string(tokens: &mut Vec<Token>) -> Result<AST, ParseError> {
+    match next(tokens) {
+        Token::String(string, span) => Ok(AST::String(string, span)),
+        Token::Template(parts, span) => {
+            let mut pieces = Vec::with_capacity(parts.len());
+            for part in parts {
+                pieces.push(match part {
+                    TemplatePart::Literal(string) => AST::String(string, span),
+                    TemplatePart::Code(mut tokens) => {
+                        tokens.reverse();
+                        let value = parse_expression(&mut tokens)?;
+                        if !matches!(peek(&tokens), Token::EOF(_)) {
+                            return Err(ParseError::UnexpectedToken(next(
+                                &mut tokens,
+                            )));
+                        }
+                        value
                     }
-                    AST::Variable(id)
-                } // _ => panic!("Unknown indentifier `{}`", id)
+                });
             }
+            Ok(AST::Interpolation(pieces, span))
         }
-        _ => panic!("Can't happen."),
+        token => Err(ParseError::Expected("string", token)),
+    }
+}
+
+fn parse_slice_bound(
+    tokens: &mut Vec<Token>,
+) -> Result<Option<Box<AST>>, ParseError> {
+    if matches!(peek(tokens), Token::Colon(_) | Token::RBracket(_)) {
+        return Ok(None);
+    }
+    Ok(Some(Box::new(parse_expression(tokens)?)))
+}
+
+fn parse_index(tokens: &mut Vec<Token>, lhs: AST) -> Result<AST, ParseError> {
+    next(tokens);
+    if let Token::RBracket(_) = peek(tokens) {
+        return Err(ParseError::Expected("index", next(tokens)));
     }
+    let start = parse_slice_bound(tokens)?;
+    let slice = matches!(peek(tokens), Token::Colon(_));
+    let end = if slice {
+        next(tokens);
+        parse_slice_bound(tokens)?
+    } else {
+        None
+    };
+    let span = match next(tokens) {
+        Token::RBracket(end) => lhs.span().to(end),
+        token => return Err(ParseError::Expected("`]`", token)),
+    };
+    Ok(match (slice, start) {
+        (false, Some(index)) => AST::Index(Box::new(lhs), index, span),
+        (_, start) => AST::Slice(Box::new(lhs), start, end, span),
+    })
 }
 
-fn parse_string(tokens: &mut Vec<Token>) -> AST {
-    match tokens.pop().unwrap() {
-        Token::String(string, _) => AST::String(string),
-        _ => panic!("Cant happen."),
+fn parse_list(tokens: &mut Vec<Token>) -> Result<AST, ParseError> {
+    let start = next(tokens).span();
+    let mut items = vec![];
+    loop {
+        if let Token::RBracket(end) = peek(tokens) {
+            let span = start.to(*end);
+            next(tokens);
+            return Ok(AST::List(items, span));
+        }
+        items.push(parse_expression(tokens)?);
+        match peek(tokens) {
+            Token::Comma(_) => {
+                next(tokens);
+            }
+            Token::RBracket(_) => {}
+            _ => return Err(ParseError::Expected("`,` or `]`", next(tokens))),
+        }
     }
 }
 
-fn parse_primary(tokens: &mut Vec<Token>) -> AST {
-    let lhs;
-    match tokens.last().unwrap() {
-        Token::Numb(_, _) => {
-            lhs = parse_number(tokens);
+fn parse_map(tokens: &mut Vec<Token>) -> Result<AST, ParseError> {
+    let start = next(tokens).span();
+    let mut entries = vec![];
+    loop {
+        if let Token::EndBlock(end) = peek(tokens) {
+            let span = start.to(*end);
+            next(tokens);
+            return Ok(AST::Map(entries, span));
         }
-        Token::LParen(_) => {
-            lhs = parse_paren(tokens);
+        // Bare identifiers are string keys, `{a: 1}` is `{"a": 1}`.
+        let key = match peek(tokens) {
+            Token::Ident(_, _)
+                if matches!(
+                    tokens.iter().nth_back(1),
+                    Some(Token::Colon(_))
+                ) =>
+            {
+                let Token::Ident(name, span) = next(tokens) else {
+                    unreachable!("Identifier was peeked above.")
+                };
+                AST::String(name, span)
+            }
+            _ => parse_expression(tokens)?,
+        };
+        match next(tokens) {
+            Token::Colon(_) => {}
+            token => return Err(ParseError::Expected("`:`", token)),
         }
-        Token::Operator(_, _) => {
-            lhs = parse_unary(tokens);
+        entries.push((key, parse_expression(tokens)?));
+        match peek(tokens) {
+            Token::Comma(_) => {
+                next(tokens);
+            }
+            Token::EndBlock(_) => {}
+            _ => return Err(ParseError::Expected("`,` or `}`", next(tokens))),
         }
-        Token::Ident(_, _) => {
-            lhs = parse_ident(tokens);
+    }
+}
+
+fn parse_postfix(tokens: &mut Vec<Token>, lhs: AST) -> Result<AST, ParseError> {
+    match peek(tokens) {
+        Token::LParen(_) => {
+            let (args, end) = parse_call(tokens)?;
+            let span = lhs.span().to(end);
+            Ok(AST::Call(Box::new(lhs), args, span))
         }
-        Token::String(_, _) => {
-            lhs = parse_string(tokens);
+        Token::LBracket(_) => parse_index(tokens, lhs),
+        _ => {
+            next(tokens);
+            match next(tokens) {
+                Token::Ident(field, end) => {
+                    let span = lhs.span().to(end);
+                    Ok(AST::Field(Box::new(lhs), field, span))
+                }
+                token => Err(ParseError::Expected("field name", token)),
+            }
         }
-        Token::Unknown => {
-            panic!("Unknown token.");
+    }
+}
+
+fn parse_primary(
+    tokens: &mut Vec<Token>,
+    struct_literals: bool,
+) -> Result<AST, ParseError> {
+    match peek(tokens) {
+        Token::Int(_, _) | Token::Numb(_, _) => parse_number(tokens),
+        Token::LParen(_) => parse_paren(tokens),
+        Token::LBracket(_) => parse_list(tokens),
+        // Code blocks only follow keywords such as `if` or `fun`, so a `{`
+        // in expression position always starts a map.
+        Token::StartBlock(_) => parse_map(tokens),
+        Token::Ident(_, _) => parse_ident(tokens, struct_literals),
+        Token::String(_, _) | Token::Template(_, _) => parse_string(tokens),
+        _ => Err(ParseError::Expected("expression", next(tokens))),
+    }
+}
+
+// The key a token is looked up by in `OPERATORS`.
+fn operator_key(token: &Token) -> Option<&str> {
+    match token {
+        Token::Operator(op, _) => Some(op),
+        Token::LParen(_) => Some("("),
+        Token::LBracket(_) => Some("["),
+        _ => None,
+    }
+}
+
+fn lookup(token: &Token, fixity: Fixity) -> Option<(u8, Assoc)> {
+    OPERATORS.get(&(fixity, operator_key(token)?)).copied()
+}
+
+fn parse_expression_bp(
+    tokens: &mut Vec<Token>,
+    min_power: u8,
+    struct_literals: bool,
+) -> Result<AST, ParseError> {
+    let mut lhs = match lookup(peek(tokens), Fixity::Prefix) {
+        Some((power, _)) => {
+            let Token::Operator(op, span) = next(tokens) else {
+                unreachable!("Only operators are prefix operators.")
+            };
+            let operand = parse_expression_bp(tokens, power, struct_literals)?;
+            let span = span.to(operand.span());
+            AST::UnaryOp(op, Box::new(operand), span)
         }
-        Token::EOF(_) => {
-            lhs = AST::Number(0.0);
-            // tokens.pop();
-            // lhs = parse_primary(tokens);
+        None => parse_primary(tokens, struct_literals)?,
+    };
+    loop {
+        if let Some((power, _)) = lookup(peek(tokens), Fixity::Postfix) {
+            if power < min_power {
+                return Ok(lhs);
+            }
+            lhs = parse_postfix(tokens, lhs)?;
+            continue;
         }
-        default => {
-            panic!(
-                "Parser error: Unexpected token. {:?}.\nRest of tokens: {:?}",
-                default, tokens
-            )
+        let Some((power, assoc)) = lookup(peek(tokens), Fixity::Infix) else {
+            return Ok(lhs);
+        };
+        if power < min_power {
+            return Ok(lhs);
         }
+        let Token::Operator(op, _) = next(tokens) else {
+            unreachable!("Only operators are infix operators.")
+        };
+        // A left associative operator only takes tighter operators on its
+        // right, a right associative one also takes itself.
+        let rhs = parse_expression_bp(
+            tokens,
+            match assoc {
+                Assoc::Left => power + 1,
+                Assoc::Right => power,
+            },
+            struct_literals,
+        )?;
+        let span = lhs.span().to(rhs.span());
+        lhs = AST::BinaryOp(op, Box::new(lhs), Box::new(rhs), span);
     }
-    lhs
 }
 
-fn parse_expression(tokens: &mut Vec<Token>) -> AST {
-    let lhs = parse_primary(tokens);
+fn parse_expression(tokens: &mut Vec<Token>) -> Result<AST, ParseError> {
+    parse_expression_bp(tokens, 0, true)
+}
 
-    parse_bin_op_rhs(tokens, lhs, 0)
+// The expression before the block of an `if`, `while`, `for` or `match`,
+// where `Name {` is the end of the expression rather than a struct literal.
+fn parse_condition(tokens: &mut Vec<Token>) -> Result<AST, ParseError> {
+    parse_expression_bp(tokens, 0, false)
 }
 
-pub fn parse(tokens: &mut Vec<Token>) -> Vec<AST> {
+fn parse_statements(tokens: &mut Vec<Token>) -> Result<Vec<AST>, ParseError> {
     let mut ast: Vec<AST> = vec![];
-    // tokens.reverse();
     loop {
-        match tokens.last().unwrap() {
-            Token::EOF(_) => return ast,
+        match peek(tokens) {
+            Token::EOF(_) | Token::EndBlock(_) => return Ok(ast),
             Token::Delim(_) => {
-                tokens.pop();
+                next(tokens);
                 continue;
             }
-            Token::EndBlock(_) => {
-                dbg!("Hello", tokens.pop(), &ast);
-                return ast;
-            }
             _ => {
-                ast.push(parse_expression(tokens));
+                ast.push(parse_expression(tokens)?);
+            }
+        }
+    }
+}
+
+pub fn parse(tokens: &mut Vec<Token>) -> Result<Vec<AST>, ParseError> {
+    let ast = parse_statements(tokens)?;
+    match peek(tokens) {
+        Token::EOF(_) => Ok(ast),
+        _ => Err(ParseError::UnexpectedToken(next(tokens))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::tokenize;
+
+    // Renders an expression as an s-expression without spans.
+    fn show(ast: &AST) -> String {
+        match ast {
+            AST::Int(x, _) => x.to_string(),
+            AST::Variable(name, _) => name.clone(),
+            AST::UnaryOp(op, operand, _) => {
+                format!("({} {})", op, show(operand))
+            }
+            AST::BinaryOp(op, lhs, rhs, _) => {
+                format!("({} {} {})", op, show(lhs), show(rhs))
+            }
+            AST::Call(function, args, _) => {
+                let args: Vec<_> = args.iter().map(show).collect();
+                format!("(call {} [{}])", show(function), args.join(" "))
+            }
+            AST::Index(target, index, _) => {
+                format!("(index {} {})", show(target), show(index))
+            }
+            AST::Field(target, field, _) => {
+                format!("(field {} {})", show(target), field)
             }
+            ast => panic!("Unexpected node {:?}", ast),
+        }
+    }
+
+    fn parse_all(source: &str) -> Vec<AST> {
+        let mut tokens = tokenize(source).unwrap();
+        tokens.reverse();
+        parse(&mut tokens).unwrap()
+    }
+
+    fn parse_str(source: &str) -> String {
+        let ast = parse_all(source);
+        assert_eq!(ast.len(), 1);
+        show(&ast[0])
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        assert_eq!(parse_str("2**3**2 == 512"), "(== (** 2 (** 3 2)) 512)");
+    }
+
+    #[test]
+    fn power_binds_tighter_than_prefix_minus() {
+        assert_eq!(parse_str("-2**2 == -4"), "(== (- (** 2 2)) (- 4))");
+    }
+
+    #[test]
+    fn arithmetic_is_left_associative() {
+        assert_eq!(parse_str("1 - 2 - 3"), "(- (- 1 2) 3)");
+        assert_eq!(parse_str("8 / 4 / 2"), "(/ (/ 8 4) 2)");
+        assert_eq!(parse_str("1 + 2 * 3 - 4"), "(- (+ 1 (* 2 3)) 4)");
+    }
+
+    #[test]
+    fn assignment_is_right_associative() {
+        assert_eq!(parse_str("a = b = 5"), "(= a (= b 5))");
+    }
+
+    #[test]
+    fn postfix_operators_chain() {
+        assert_eq!(
+            parse_str("a.b(c).d[0]"),
+            "(index (field (call (field a b) [c]) d) 0)"
+        );
+        assert_eq!(parse_str("-f(x)[1]"), "(- (index (call f [x]) 1))");
+    }
+
+    #[test]
+    fn struct_literals_need_not_be_capitalized() {
+        let ast = parse_all("let p = point { x: 1 }");
+        let [AST::Let(_, value, _)] = &ast[..] else {
+            panic!("Expected a let, found {:?}", ast);
+        };
+        let AST::StructLiteral(name, _, _) = &**value else {
+            panic!("Expected a struct literal, found {:?}", value);
+        };
+        assert_eq!(name, "point");
+    }
+
+    #[test]
+    fn conditions_end_before_the_block() {
+        let ast = parse_all("if Flag {} else { 2 }");
+        let [AST::If(condition, body, Some(_), _)] = &ast[..] else {
+            panic!("Expected an if, found {:?}", ast);
+        };
+        assert_eq!(show(condition), "Flag");
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn jumps_without_a_value_end_a_match_arm() {
+        for source in [
+            "match 1 { 1 -> break, _ -> 0 }",
+            "match 1 { 1 -> return, _ -> 0 }",
+            "match 1 { 1 -> continue, _ -> 0 }",
+        ] {
+            let ast = parse_all(source);
+            let [AST::Match(_, arms, _)] = &ast[..] else {
+                panic!("Expected a match, found {:?}", ast);
+            };
+            assert_eq!(arms.len(), 2);
+            assert!(matches!(
+                &arms[0].2[..],
+                [
+                    AST::Break(None, _)
+                        | AST::Return(None, _)
+                        | AST::Continue(_)
+                ]
+            ));
         }
     }
 }
@@ -1,47 +1,194 @@
 extern crate regex_macro;
 
+use crate::input::{Diagnostic, Span};
+use num_bigint::BigInt;
 use std::char;
+use std::fmt;
 use std::iter::Peekable;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Token {
-    Ident(String, usize),
-    Numb(f64, usize),
-    Operator(String, usize),
-    Delim(usize),
-    LParen(usize),
-    RParen(usize),
-    EOF(usize),
-    String(String, usize),
-    StartBlock(usize),
-    EndBlock(usize),
-    Unknown,
+    Ident(String, Span),
+    Int(BigInt, Span),
+    Numb(f64, Span),
+    Operator(String, Span),
+    Delim(Span),
+    LParen(Span),
+    RParen(Span),
+    LBracket(Span),
+    RBracket(Span),
+    Colon(Span),
+    Comma(Span),
+    EOF(Span),
+    String(String, Span),
+    Template(Vec<TemplatePart>, Span),
+    StartBlock(Span),
+    EndBlock(Span),
+}
+
+// A piece of an interpolated string, `"x = {x}"` is the literal `x = `
+// followed by the tokens of `x`.
+#[derive(Debug, Clone)]
+pub enum TemplatePart {
+    Literal(String),
+    Code(Vec<Token>),
+}
+
+impl Token {
+    pub fn span(&self) -> Span {
+        match self {
+            Token::Ident(_, span)
+            | Token::Int(_, span)
+            | Token::Numb(_, span)
+            | Token::Operator(_, span)
+            | Token::Delim(span)
+            | Token::LParen(span)
+            | Token::RParen(span)
+            | Token::LBracket(span)
+            | Token::RBracket(span)
+            | Token::Colon(span)
+            | Token::Comma(span)
+            | Token::EOF(span)
+            | Token::String(_, span)
+            | Token::Template(_, span)
+            | Token::StartBlock(span)
+            | Token::EndBlock(span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(id, _) => write!(f, "identifier `{}`", id),
+            Token::Int(n, _) => write!(f, "number `{}`", n),
+            Token::Numb(n, _) => write!(f, "number `{:?}`", n),
+            Token::Operator(op, _) => write!(f, "operator `{}`", op),
+            Token::Delim(_) => write!(f, "`;`"),
+            Token::LParen(_) => write!(f, "`(`"),
+            Token::RParen(_) => write!(f, "`)`"),
+            Token::LBracket(_) => write!(f, "`[`"),
+            Token::RBracket(_) => write!(f, "`]`"),
+            Token::Colon(_) => write!(f, "`:`"),
+            Token::Comma(_) => write!(f, "`,`"),
+            Token::EOF(_) => write!(f, "end of input"),
+            Token::String(s, _) => write!(f, "string {:?}", s),
+            Token::Template(_, _) => write!(f, "string template"),
+            Token::StartBlock(_) => write!(f, "`{{`"),
+            Token::EndBlock(_) => write!(f, "`}}`"),
+        }
+    }
 }
 
+#[derive(Debug)]
+pub enum LexError {
+    UnexpectedChar(char, Span),
+    InvalidNumber(String, Span),
+    InvalidDigit(char, &'static str, Span),
+    MissingDigits(&'static str, Span),
+    UnknownEscape(char, Span),
+    InvalidEscape(String, Span),
+    UnterminatedString(Span),
+    UnterminatedInterpolation(Span),
+    UnterminatedComment(Span),
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnexpectedChar(_, span)
+            | LexError::InvalidNumber(_, span)
+            | LexError::InvalidDigit(_, _, span)
+            | LexError::MissingDigits(_, span)
+            | LexError::UnknownEscape(_, span)
+            | LexError::InvalidEscape(_, span)
+            | LexError::UnterminatedString(span)
+            | LexError::UnterminatedInterpolation(span)
+            | LexError::UnterminatedComment(span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::UnexpectedChar(c, _) => {
+                write!(f, "Unexpected character '{}'.", c)
+            }
+            LexError::InvalidNumber(n, _) => {
+                write!(f, "'{}' is not a valid number.", n)
+            }
+            LexError::InvalidDigit(c, kind, _) => {
+                write!(f, "Invalid digit '{}' in {} literal.", c, kind)
+            }
+            LexError::MissingDigits(after, _) => {
+                write!(f, "Expected digits after {}.", after)
+            }
+            LexError::UnknownEscape(c, _) => {
+                write!(f, "Unknown escape sequence '\\{}'.", c)
+            }
+            LexError::InvalidEscape(escape, _) => {
+                write!(f, "Invalid escape sequence '\\{}'.", escape)
+            }
+            LexError::UnterminatedString(_) => {
+                write!(f, "Unterminated string literal.")
+            }
+            LexError::UnterminatedInterpolation(_) => {
+                write!(f, "Unterminated `{{` in string literal.")
+            }
+            LexError::UnterminatedComment(_) => {
+                write!(f, "Unterminated block comment.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LexError {}
+
+impl From<LexError> for Diagnostic {
+    fn from(error: LexError) -> Diagnostic {
+        Diagnostic::new(error.to_string(), error.span())
+    }
+}
+
+const OPERATORS: [&str; 19] = [
+    "+", "-", "*", "/", "%", "=", "<", ">", "!", "**", "==", "!=", "<=", ">=",
+    "&&", "||", "->", "..", ".",
+];
+
 #[derive(Debug)]
 enum State {
     Start,
     NumberWhole,
     NumberDecimal,
+    NumberExponent,
+    NumberRadix(u32),
+    LineComment,
+    BlockComment(usize),
     Ident,
-    String,
-    StringEscape,
+    String(usize),
+    StringEscape(usize),
+    RawString(usize),
 }
 
 struct Tokenizer<'a> {
     input: Peekable<std::str::Chars<'a>>,
     state: State,
     curent: String,
+    parts: Vec<TemplatePart>,
+    line_starts: Vec<(usize, usize)>,
     position: usize,
     start_pos: usize,
 }
 
 impl<'a> Tokenizer<'a> {
-    fn new(input: &str) -> Tokenizer {
+    fn new(input: &str) -> Tokenizer<'_> {
         Tokenizer {
             input: input.chars().peekable(),
             state: State::Start,
             curent: String::new(),
+            parts: Vec::new(),
+            line_starts: Vec::new(),
             position: 0,
             start_pos: 0,
         }
@@ -50,8 +197,192 @@ impl<'a> Tokenizer<'a> {
         self.position += 1;
         self.input.next().expect("A char was expected.")
     }
-    fn next_token(&mut self) -> Token {
-        while let Some(c) = self.input.peek() {
+    fn span(&self) -> Span {
+        Span::new(self.start_pos, self.position)
+    }
+    fn single(&mut self, token: fn(Span) -> Token) -> Token {
+        self.consume_char();
+        let t = token(self.span());
+        self.start_pos = self.position;
+        t
+    }
+    fn operator(&mut self) -> Result<Token, LexError> {
+        let mut op = self.consume_char().to_string();
+        while let Some(&c) = self.input.peek() {
+            let candidate = format!("{}{}", op, c);
+            if !OPERATORS.iter().any(|o| o.starts_with(&candidate)) {
+                break;
+            }
+            self.consume_char();
+            op = candidate;
+        }
+        if !OPERATORS.contains(&op.as_str()) {
+            return Err(LexError::UnexpectedChar(
+                op.chars().next().unwrap_or_default(),
+                self.span(),
+            ));
+        }
+        let t = Token::Operator(op, self.span());
+        self.start_pos = self.position;
+        Ok(t)
+    }
+    fn invalid_digit(&self, c: char) -> LexError {
+        let kind = match self.state {
+            State::NumberRadix(16) => "hexadecimal",
+            State::NumberRadix(8) => "octal",
+            State::NumberRadix(2) => "binary",
+            _ => "decimal",
+        };
+        LexError::InvalidDigit(
+            c,
+            kind,
+            Span::new(self.position, self.position + 1),
+        )
+    }
+    fn finish_number(&mut self) -> Result<Token, LexError> {
+        let span = self.span();
+        let t = match self.state {
+            State::NumberRadix(radix) => {
+                if self.curent.is_empty() {
+                    let prefix = match radix {
+                        16 => "`0x`",
+                        8 => "`0o`",
+                        _ => "`0b`",
+                    };
+                    return Err(LexError::MissingDigits(prefix, span));
+                }
+                BigInt::parse_bytes(self.curent.as_bytes(), radix)
+                    .map(|n| Token::Int(n, span))
+            }
+            State::NumberExponent
+                if !self.curent.ends_with(|c: char| c.is_ascii_digit()) =>
+            {
+                return Err(LexError::MissingDigits("exponent `e`", span));
+            }
+            State::NumberWhole => {
+                self.curent.parse().ok().map(|n| Token::Int(n, span))
+            }
+            _ => self.curent.parse().ok().map(|n| Token::Numb(n, span)),
+        }
+        .ok_or_else(|| LexError::InvalidNumber(self.curent.clone(), span))?;
+        self.state = State::Start;
+        self.start_pos = self.position;
+        self.curent.clear();
+        Ok(t)
+    }
+    fn interpolation(&mut self) -> Result<(), LexError> {
+        let start = self.position;
+        self.consume_char();
+        let mut code = String::new();
+        let mut depth = 0;
+        let mut quoted = false;
+        loop {
+            let Some(c) = self.input.next() else {
+                return Err(LexError::UnterminatedInterpolation(Span::new(
+                    start,
+                    self.position,
+                )));
+            };
+            self.position += 1;
+            match c {
+                '}' if depth == 0 && !quoted => break,
+                '\\' if quoted => {
+                    code.push(c);
+                    if let Some(c) = self.input.next() {
+                        self.position += 1;
+                        code.push(c);
+                    }
+                    continue;
+                }
+                '"' => quoted = !quoted,
+                '{' if !quoted => depth += 1,
+                '}' if !quoted => depth -= 1,
+                _ => {}
+            }
+            code.push(c);
+        }
+        let literal = std::mem::take(&mut self.curent);
+        self.parts.push(TemplatePart::Literal(literal));
+        let tokens = tokenize_at(&code, start + 1)?;
+        self.parts.push(TemplatePart::Code(tokens));
+        Ok(())
+    }
+    fn hex_escape(&mut self) -> Result<char, LexError> {
+        let start = self.position - 1;
+        let mut escape = self.consume_char().to_string();
+        while escape.len() < 3 {
+            match self.input.peek() {
+                Some(c) if c.is_ascii_hexdigit() => {
+                    let c = self.consume_char();
+                    escape.push(c);
+                }
+                _ => break,
+            }
+        }
+        match u8::from_str_radix(&escape[1..], 16) {
+            // Like Rust, `\xNN` only covers ASCII, use `\u{..}` for the rest.
+            Ok(n) if escape.len() == 3 && n.is_ascii() => Ok(n as char),
+            _ => Err(LexError::InvalidEscape(
+                escape,
+                Span::new(start, self.position),
+            )),
+        }
+    }
+    fn unicode_escape(&mut self) -> Result<char, LexError> {
+        let start = self.position - 1;
+        let mut escape = self.consume_char().to_string();
+        if self.input.peek() == Some(&'{') {
+            while let Some(&c) = self.input.peek() {
+                if !(c.is_ascii_hexdigit() || c == '{') || escape.len() > 8 {
+                    break;
+                }
+                escape.push(self.consume_char());
+            }
+            if self.input.peek() == Some(&'}') {
+                escape.push(self.consume_char());
+                if let Some(c) =
+                    u32::from_str_radix(&escape[2..escape.len() - 1], 16)
+                        .ok()
+                        .filter(|_| escape.len() > 3)
+                        .and_then(char::from_u32)
+                {
+                    return Ok(c);
+                }
+            }
+        }
+        Err(LexError::InvalidEscape(
+            escape,
+            Span::new(start, self.position),
+        ))
+    }
+    fn finish_string(&mut self) -> Token {
+        let literal = std::mem::take(&mut self.curent);
+        self.parts.push(TemplatePart::Literal(literal));
+        let mut parts = std::mem::take(&mut self.parts);
+        dedent(&mut parts, &std::mem::take(&mut self.line_starts));
+        parts.retain(
+            |part| !matches!(part, TemplatePart::Literal(s) if s.is_empty()),
+        );
+        let span = self.span();
+        self.state = State::Start;
+        self.start_pos = self.position;
+        match parts.as_mut_slice() {
+            [] => Token::String(String::new(), span),
+            [TemplatePart::Literal(literal)] => {
+                Token::String(std::mem::take(literal), span)
+            }
+            _ => Token::Template(parts, span),
+        }
+    }
+    fn finish_ident(&mut self) -> Token {
+        let t = Token::Ident(self.curent.clone(), self.span());
+        self.curent.clear();
+        self.state = State::Start;
+        self.start_pos = self.position;
+        t
+    }
+    fn next_token(&mut self) -> Result<Token, LexError> {
+        while let Some(&c) = self.input.peek() {
             match self.state {
                 State::Start => match c {
                     '0'..='9' => {
@@ -64,182 +395,479 @@ impl<'a> Tokenizer<'a> {
                         self.consume_char();
                         self.start_pos = self.position;
                     }
-                    op if ['+', '-', '%', '/', '=', '*'].contains(op) => {
-                        let t = Token::Operator(op.to_string(), self.start_pos);
+                    '/' if self.input.clone().nth(1) == Some('/') => {
+                        self.state = State::LineComment;
+                    }
+                    '/' if self.input.clone().nth(1) == Some('*') => {
                         self.consume_char();
-                        self.start_pos = self.position;
-                        return t;
+                        self.consume_char();
+                        self.state = State::BlockComment(1);
+                    }
+                    '#' if self.position == 0
+                        && self.input.clone().nth(1) == Some('!') =>
+                    {
+                        self.state = State::LineComment;
+                    }
+                    op if OPERATORS.iter().any(|o| o.starts_with(op)) => {
+                        return self.operator()
                     }
-                    '(' => {
-                        let t = Token::LParen(self.start_pos);
+                    '(' => return Ok(self.single(Token::LParen)),
+                    ')' => return Ok(self.single(Token::RParen)),
+                    '[' => return Ok(self.single(Token::LBracket)),
+                    ']' => return Ok(self.single(Token::RBracket)),
+                    ':' => return Ok(self.single(Token::Colon)),
+                    ',' => return Ok(self.single(Token::Comma)),
+                    '{' => return Ok(self.single(Token::StartBlock)),
+                    '}' => return Ok(self.single(Token::EndBlock)),
+                    ';' => return Ok(self.single(Token::Delim)),
+                    '"' if self.input.clone().take(3).eq("\"\"\"".chars()) => {
+                        for _ in 0..3 {
+                            self.consume_char();
+                        }
+                        // A line break right after the opening quotes is not
+                        // part of the string.
+                        if self.input.peek() == Some(&'\r') {
+                            self.consume_char();
+                        }
+                        if self.input.peek() == Some(&'\n') {
+                            self.consume_char();
+                            self.line_starts.push((0, 0));
+                        }
+                        self.state = State::String(3);
+                    }
+                    '"' => {
                         self.consume_char();
-                        self.start_pos = self.position;
-                        return t;
+                        self.state = State::String(1);
                     }
-                    ')' => {
-                        let t = Token::RParen(self.start_pos);
+                    'r' if matches!(
+                        self.input.clone().nth(1),
+                        Some('"') | Some('#')
+                    ) =>
+                    {
                         self.consume_char();
-                        self.start_pos = self.position;
-                        return t;
+                        let mut hashes = 0;
+                        while self.input.peek() == Some(&'#') {
+                            self.consume_char();
+                            hashes += 1;
+                        }
+                        match self.input.peek() {
+                            Some('"') => {
+                                self.consume_char();
+                                self.state = State::RawString(hashes);
+                            }
+                            Some(&c) => {
+                                return Err(LexError::UnexpectedChar(
+                                    c,
+                                    Span::new(self.position, self.position + 1),
+                                ))
+                            }
+                            None => {
+                                return Err(LexError::UnterminatedString(
+                                    self.span(),
+                                ))
+                            }
+                        }
+                    }
+                    c if c.is_alphabetic() || c == '_' => {
+                        let c = self.consume_char();
+                        self.curent.push(c);
+                        self.state = State::Ident
                     }
-                    '{' => {
-                        let t = Token::StartBlock(self.start_pos);
+                    c => {
+                        return Err(LexError::UnexpectedChar(
+                            c,
+                            Span::new(self.start_pos, self.start_pos + 1),
+                        ))
+                    }
+                },
+                State::NumberWhole => match c {
+                    '0'..='9' => {
+                        let c = self.consume_char();
+                        self.curent.push(c);
+                    }
+                    '_' => {
                         self.consume_char();
-                        self.start_pos = self.position;
-                        return t;
                     }
-                    '}' => {
-                        let t = Token::EndBlock(self.start_pos);
+                    'x' | 'o' | 'b' if self.curent == "0" => {
                         self.consume_char();
-                        self.start_pos = self.position;
-                        return t;
+                        self.curent.clear();
+                        self.state = State::NumberRadix(match c {
+                            'x' => 16,
+                            'o' => 8,
+                            _ => 2,
+                        });
                     }
-                    c if c.is_alphabetic() || c == &'_' => {
+                    '.' if self
+                        .input
+                        .clone()
+                        .nth(1)
+                        .is_some_and(|c| c.is_ascii_digit()) =>
+                    {
+                        self.state = State::NumberDecimal;
                         let c = self.consume_char();
                         self.curent.push(c);
-                        self.state = State::Ident
                     }
-                    ';' => {
-                        let t = Token::Delim(self.start_pos);
+                    'e' | 'E' => {
+                        self.state = State::NumberExponent;
                         self.consume_char();
-                        self.start_pos = self.position;
-                        return t;
+                        self.curent.push('e');
                     }
-                    '"' => {
+                    c if c.is_alphanumeric() => {
+                        return Err(self.invalid_digit(c))
+                    }
+                    _ => return self.finish_number(),
+                },
+                State::NumberDecimal => match c {
+                    '0'..='9' => {
+                        let c = self.consume_char();
+                        self.curent.push(c);
+                    }
+                    '_' => {
+                        self.consume_char();
+                    }
+                    '.' if self
+                        .input
+                        .clone()
+                        .nth(1)
+                        .is_some_and(|c| c.is_ascii_digit()) =>
+                    {
+                        while let Some(&c) = self.input.peek() {
+                            if !c.is_ascii_digit() && c != '.' && c != '_' {
+                                break;
+                            }
+                            let c = self.consume_char();
+                            self.curent.push(c);
+                        }
+                        return Err(LexError::InvalidNumber(
+                            self.curent.clone(),
+                            self.span(),
+                        ));
+                    }
+                    'e' | 'E' => {
+                        self.state = State::NumberExponent;
                         self.consume_char();
-                        self.state = State::String;
+                        self.curent.push('e');
+                    }
+                    c if c.is_alphanumeric() => {
+                        return Err(self.invalid_digit(c))
                     }
-                    c => todo!(
-                        "Tokens starting with '{}' are not implemented.",
-                        c
-                    ),
+                    _ => return self.finish_number(),
                 },
-                State::NumberWhole => match c {
+                State::NumberExponent => match c {
+                    '+' | '-' if self.curent.ends_with('e') => {
+                        let c = self.consume_char();
+                        self.curent.push(c);
+                    }
                     '0'..='9' => {
                         let c = self.consume_char();
                         self.curent.push(c);
                     }
-                    '.' => {
-                        self.state = State::NumberDecimal;
+                    '_' => {
+                        self.consume_char();
+                    }
+                    c if c.is_alphanumeric() => {
+                        return Err(self.invalid_digit(c))
+                    }
+                    _ => return self.finish_number(),
+                },
+                State::NumberRadix(radix) => match c {
+                    c if c.is_digit(radix) => {
                         let c = self.consume_char();
                         self.curent.push(c);
                     }
-                    _ => {
-                        self.state = State::Start;
-                        let t = Token::Numb(
-                            self.curent.parse().unwrap_or_else(|_| {
-                                panic!(
-                                    "'{}' is not a valid number.",
-                                    self.curent
-                                )
-                            }),
-                            self.start_pos,
-                        );
-                        self.start_pos = self.position;
-                        self.curent.clear();
-                        return t;
+                    '_' => {
+                        self.consume_char();
+                    }
+                    c if c.is_alphanumeric() => {
+                        return Err(self.invalid_digit(c))
                     }
+                    _ => return self.finish_number(),
                 },
-                State::NumberDecimal => match c {
-                    '0'..='9' => self.curent.push(self.input.next().unwrap()),
-                    _ => {
+                State::LineComment => {
+                    if c == '\n' {
                         self.state = State::Start;
-                        let t = Token::Numb(
-                            self.curent.parse().unwrap(),
-                            self.start_pos,
-                        );
                         self.start_pos = self.position;
-                        self.curent.clear();
-                        return t;
+                    } else {
+                        self.consume_char();
                     }
-                },
+                }
+                State::BlockComment(depth) => {
+                    let next = self.input.clone().nth(1);
+                    self.consume_char();
+                    if c == '*' && next == Some('/') {
+                        self.consume_char();
+                        self.state = match depth {
+                            1 => {
+                                self.start_pos = self.position;
+                                State::Start
+                            }
+                            _ => State::BlockComment(depth - 1),
+                        };
+                    } else if c == '/' && next == Some('*') {
+                        self.consume_char();
+                        self.state = State::BlockComment(depth + 1);
+                    }
+                }
                 State::Ident => match c {
                     c if c.is_alphabetic()
                         || c.is_ascii_digit()
-                        || c == &'_' =>
+                        || c == '_' =>
                     {
                         let c = self.consume_char();
                         self.curent.push(c);
                     }
-                    _ => {
-                        let t =
-                            Token::Ident(self.curent.clone(), self.start_pos);
-                        self.curent.clear();
-                        self.state = State::Start;
-                        self.start_pos = self.position;
-                        return t;
-                    }
+                    _ => return Ok(self.finish_ident()),
                 },
-                State::String => match c {
+                State::String(quotes) => match c {
                     '\\' => {
-                        self.state = State::StringEscape;
+                        self.state = State::StringEscape(quotes);
                         self.consume_char();
                     }
-                    '"' => {
-                        self.state = State::Start;
-                        let t =
-                            Token::String(self.curent.clone(), self.start_pos);
+                    '"' if quotes == 1
+                        || self.input.clone().take(3).eq("\"\"\"".chars()) =>
+                    {
+                        for _ in 0..quotes {
+                            self.consume_char();
+                        }
+                        return Ok(self.finish_string());
+                    }
+                    '{' | '}' if self.input.clone().nth(1) == Some(c) => {
                         self.consume_char();
-                        self.start_pos = self.position;
-                        self.curent.clear();
-                        return t;
+                        let c = self.consume_char();
+                        self.curent.push(c);
+                    }
+                    '{' => self.interpolation()?,
+                    '}' => {
+                        return Err(LexError::UnexpectedChar(
+                            c,
+                            Span::new(self.position, self.position + 1),
+                        ))
                     }
                     _ => {
                         let c = self.consume_char();
                         self.curent.push(c);
+                        if c == '\n' && quotes == 3 {
+                            self.line_starts
+                                .push((self.parts.len(), self.curent.len()));
+                        }
                     }
                 },
-                State::StringEscape => match c {
+                State::RawString(hashes) => {
+                    let c = self.consume_char();
+                    if c == '"'
+                        && self
+                            .input
+                            .clone()
+                            .take(hashes)
+                            .filter(|&c| c == '#')
+                            .count()
+                            == hashes
+                    {
+                        for _ in 0..hashes {
+                            self.consume_char();
+                        }
+                        return Ok(self.finish_string());
+                    }
+                    self.curent.push(c);
+                }
+                State::StringEscape(quotes) => match c {
                     '\\' => {
-                        self.state = State::String;
+                        self.state = State::String(quotes);
                         let c = self.consume_char();
                         self.curent.push(c);
                     }
                     '"' => {
-                        self.state = State::String;
+                        self.state = State::String(quotes);
                         let c = self.consume_char();
                         self.curent.push(c);
                     }
                     'n' => {
-                        self.state = State::String;
+                        self.state = State::String(quotes);
                         self.consume_char();
                         self.curent.push('\n');
                     }
                     't' => {
-                        self.state = State::String;
+                        self.state = State::String(quotes);
                         self.consume_char();
                         self.curent.push('\t');
                     }
                     'v' => {
-                        self.state = State::String;
+                        self.state = State::String(quotes);
                         self.consume_char();
                         self.curent.push('\x0B');
                     }
                     'r' => {
-                        self.state = State::String;
+                        self.state = State::String(quotes);
                         self.consume_char();
                         self.curent.push('\r');
                     }
-                    _ => todo!("Unknown escape pattern."),
+                    '0' => {
+                        self.state = State::String(quotes);
+                        self.consume_char();
+                        self.curent.push('\0');
+                    }
+                    'x' => {
+                        self.state = State::String(quotes);
+                        let c = self.hex_escape()?;
+                        self.curent.push(c);
+                    }
+                    'u' => {
+                        self.state = State::String(quotes);
+                        let c = self.unicode_escape()?;
+                        self.curent.push(c);
+                    }
+                    c => {
+                        return Err(LexError::UnknownEscape(
+                            c,
+                            Span::new(self.position - 1, self.position + 1),
+                        ))
+                    }
                 },
             }
         }
-        Token::EOF(self.position)
+        match self.state {
+            State::Start | State::LineComment => {
+                Ok(Token::EOF(Span::new(self.position, self.position)))
+            }
+            State::BlockComment(_) => {
+                Err(LexError::UnterminatedComment(self.span()))
+            }
+            State::NumberWhole
+            | State::NumberDecimal
+            | State::NumberExponent
+            | State::NumberRadix(_) => self.finish_number(),
+            State::Ident => Ok(self.finish_ident()),
+            State::String(_) | State::StringEscape(_) | State::RawString(_) => {
+                Err(LexError::UnterminatedString(self.span()))
+            }
+        }
+    }
+}
+
+// Strips the indentation shared by every line of a triple-quoted string.
+// Lines are located by `(part, offset)` pairs pointing just past each line
+// break. A last line holding nothing but the indentation of the closing quotes
+// is dropped and sets how much indentation is removed.
+fn dedent(parts: &mut [TemplatePart], line_starts: &[(usize, usize)]) {
+    let width = |literal: &str| {
+        literal.len() - literal.trim_start_matches([' ', '\t']).len()
+    };
+    let count = parts.len();
+    let mut lines = line_starts;
+    let mut indent = usize::MAX;
+    for (i, &(part, offset)) in line_starts.iter().enumerate() {
+        let TemplatePart::Literal(literal) = &mut parts[part] else {
+            continue;
+        };
+        let line = &literal[offset..];
+        let rest = &line[width(line)..];
+        let last = i + 1 == line_starts.len();
+        if last && rest.is_empty() && part + 1 == count {
+            indent = indent.min(width(line));
+            literal.truncate(offset);
+            if literal.ends_with('\n') {
+                literal.pop();
+            }
+            if literal.ends_with('\r') {
+                literal.pop();
+            }
+            lines = &line_starts[..i];
+        } else if !rest.starts_with(['\n', '\r']) {
+            indent = indent.min(width(line));
+        }
+    }
+    for &(part, offset) in lines.iter().rev() {
+        if let TemplatePart::Literal(literal) = &mut parts[part] {
+            let strip = width(&literal[offset..]).min(indent);
+            literal.replace_range(offset..offset + strip, "");
+        }
     }
 }
 
-pub fn tokenize(input: &str) -> Vec<Token> {
+pub fn tokenize(input: &str) -> Result<Vec<Token>, LexError> {
+    tokenize_at(input, 0)
+}
+
+// Tokenizes `input` as if it started at `offset` in the enclosing source, so
+// interpolated expressions report spans inside their string literal.
+fn tokenize_at(input: &str, offset: usize) -> Result<Vec<Token>, LexError> {
     let mut out = Vec::new();
     let mut tokenizer = Tokenizer::new(input);
-    let mut eof = false;
+    tokenizer.position = offset;
+    tokenizer.start_pos = offset;
     loop {
-        let t = tokenizer.next_token();
+        let t = tokenizer.next_token()?;
         if matches!(t, Token::EOF(_)) {
-            if eof {
-                return out;
-            }
-            eof = true;
+            out.push(t);
+            return Ok(out);
         }
         out.push(t);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_decimal_point_is_invalid() {
+        assert!(matches!(
+            tokenize("1.2.3"),
+            Err(LexError::InvalidNumber(_, _))
+        ));
+    }
+
+    #[test]
+    fn radix_prefix_needs_digits() {
+        assert!(matches!(
+            tokenize("0x"),
+            Err(LexError::MissingDigits("`0x`", _))
+        ));
+    }
+
+    #[test]
+    fn digit_outside_radix_is_invalid() {
+        assert!(matches!(
+            tokenize("0b102"),
+            Err(LexError::InvalidDigit('2', "binary", _))
+        ));
+    }
+
+    #[test]
+    fn hex_escapes_are_ascii() {
+        assert!(matches!(
+            &tokenize(r#""\x41\x7F""#).unwrap()[0],
+            Token::String(x, _) if x == "A\x7F"
+        ));
+        assert!(matches!(
+            tokenize(r#""\xC3\xA9""#),
+            Err(LexError::InvalidEscape(x, _)) if x == "xC3"
+        ));
+    }
+
+    fn string(source: &str) -> String {
+        match &tokenize(source).unwrap()[0] {
+            Token::String(x, _) => x.clone(),
+            token => panic!("Expected a string, found {:?}", token),
+        }
+    }
+
+    #[test]
+    fn triple_quoted_strings_are_dedented() {
+        let source = "\"\"\"\n    a\n      b\n\n    c\n    \"\"\"";
+        assert_eq!(string(source), "a\n  b\n\nc");
+        assert_eq!(string("\"\"\"x\n  y\"\"\""), "x\ny");
+        assert_eq!(string("\"\"\"\n  a\r\n  b\r\n  \"\"\""), "a\r\nb");
+    }
+
+    #[test]
+    fn dedent_keeps_interpolations() {
+        let source = "\"\"\"\n    x = {x}\n      y\n    \"\"\"";
+        let Token::Template(parts, _) = &tokenize(source).unwrap()[0] else {
+            panic!("Expected a template");
+        };
+        assert!(matches!(&parts[0], TemplatePart::Literal(x) if x == "x = "));
+        assert!(matches!(&parts[1], TemplatePart::Code(_)));
+        assert!(matches!(&parts[2], TemplatePart::Literal(x) if x == "\n  y"));
+    }
+}